@@ -0,0 +1,190 @@
+use std::env;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+use crate::constants::{APP_NAME, SSH_AUTH_SOCK_ENV_VAR};
+use crate::keys;
+use crate::store::FileEntry;
+use crate::utils;
+
+const SSH_AGENT_SUCCESS: u8 = 6;
+const SSH2_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH2_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH2_AGENTC_ADD_IDENTITY: u8 = 17;
+const SSH2_AGENTC_REMOVE_IDENTITY: u8 = 18;
+const SSH2_AGENTC_ADD_ID_CONSTRAINED: u8 = 25;
+const SSH_AGENT_CONSTRAIN_LIFETIME: u8 = 1;
+
+const MAX_RESPONSE_LEN: usize = 256 * 1024;
+
+#[derive(Debug)]
+pub struct AgentIdentity {
+    pub blob: Vec<u8>,
+    pub comment: String,
+}
+
+/// A connection to the ssh-agent listening on `$SSH_AUTH_SOCK`.
+pub struct Agent {
+    stream: UnixStream,
+}
+
+impl Agent {
+    pub fn connect() -> Option<Agent> {
+        let socket_path = env::var(SSH_AUTH_SOCK_ENV_VAR).ok()?;
+        let stream = UnixStream::connect(socket_path).ok()?;
+        stream.set_read_timeout(Some(Duration::from_secs(5))).ok()?;
+
+        Some(Agent { stream })
+    }
+
+    fn request(&mut self, message_type: u8, payload: &[u8]) -> Result<(u8, Vec<u8>), String> {
+        let agent_err = |_| format!("{}: Error: Could not talk to ssh-agent.", APP_NAME);
+
+        let mut message: Vec<u8> = vec![];
+        message.extend_from_slice(&(payload.len() as u32 + 1).to_be_bytes());
+        message.push(message_type);
+        message.extend_from_slice(payload);
+        self.stream.write_all(&message).map_err(agent_err)?;
+
+        let mut len_bytes = [0u8; 4];
+        self.stream.read_exact(&mut len_bytes).map_err(agent_err)?;
+        let len = u32::from_be_bytes(len_bytes) as usize;
+
+        if len == 0 || len > MAX_RESPONSE_LEN {
+            return Err(format!("{}: Error: Invalid ssh-agent response.", APP_NAME));
+        }
+
+        let mut response = vec![0u8; len];
+        self.stream.read_exact(&mut response).map_err(agent_err)?;
+
+        Ok((response[0], response[1..].to_vec()))
+    }
+
+    pub fn list_identities(&mut self) -> Result<Vec<AgentIdentity>, String> {
+        let (response_type, data) = self.request(SSH2_AGENTC_REQUEST_IDENTITIES, &[])?;
+        let invalid_msg = || format!("{}: Error: Invalid ssh-agent response.", APP_NAME);
+
+        if response_type != SSH2_AGENT_IDENTITIES_ANSWER || data.len() < 4 {
+            return Err(invalid_msg());
+        }
+
+        let count = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let mut rest = &data[4..];
+        let mut identities: Vec<AgentIdentity> = vec![];
+
+        for _ in 0..count {
+            let (blob, after_blob) = keys::read_ssh_string(rest).ok_or_else(invalid_msg)?;
+            let (comment, after_comment) =
+                keys::read_ssh_string(after_blob).ok_or_else(invalid_msg)?;
+            rest = after_comment;
+
+            identities.push(AgentIdentity {
+                blob: blob.to_vec(),
+                comment: String::from_utf8_lossy(comment).into_owned(),
+            });
+        }
+
+        Ok(identities)
+    }
+
+    pub fn remove_identity(&mut self, blob: &[u8]) -> Result<bool, String> {
+        let mut payload: Vec<u8> = vec![];
+        keys::push_ssh_string(&mut payload, blob);
+
+        let (response_type, _) = self.request(SSH2_AGENTC_REMOVE_IDENTITY, &payload)?;
+        Ok(response_type == SSH_AGENT_SUCCESS)
+    }
+
+    /// Adds a key given as agent-protocol key fields, optionally for a
+    /// limited number of seconds.
+    pub fn add_identity(
+        &mut self,
+        key_fields: &[u8],
+        lifetime: Option<u32>,
+    ) -> Result<bool, String> {
+        let mut payload: Vec<u8> = key_fields.to_vec();
+
+        let message_type = match lifetime {
+            Some(seconds) => {
+                payload.push(SSH_AGENT_CONSTRAIN_LIFETIME);
+                payload.extend_from_slice(&seconds.to_be_bytes());
+                SSH2_AGENTC_ADD_ID_CONSTRAINED
+            }
+            None => SSH2_AGENTC_ADD_IDENTITY,
+        };
+
+        let (response_type, _) = self.request(message_type, &payload)?;
+        Ok(response_type == SSH_AGENT_SUCCESS)
+    }
+}
+
+/// Removes the keys of `outgoing_entries` from the agent and adds the keys of
+/// `incoming_entries`, returning what changed. Returns `None` when the agent
+/// is not reachable, the profile switch itself has already happened.
+pub fn switch_identities(
+    outgoing_entries: &[FileEntry],
+    incoming_entries: &[FileEntry],
+    lifetime: Option<u32>,
+) -> Option<Vec<String>> {
+    let mut agent = Agent::connect()?;
+
+    let loaded_blobs: Vec<Vec<u8>> = agent
+        .list_identities()
+        .map(|identities| {
+            identities
+                .into_iter()
+                .map(|identity| identity.blob)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut messages: Vec<String> = vec![];
+
+    for entry in outgoing_entries {
+        if !utils::is_private_key_file_name(&entry.name) {
+            continue;
+        }
+
+        let Some(blob) = keys::get_private_key_blob(outgoing_entries, &entry.name) else {
+            continue;
+        };
+
+        if loaded_blobs.contains(&blob) && agent.remove_identity(&blob).unwrap_or(false) {
+            messages.push(format!(
+                "removed  {:<16}{}",
+                entry.name,
+                keys::get_fingerprint(&blob)
+            ));
+        }
+    }
+
+    for entry in incoming_entries {
+        if !utils::is_private_key_file_name(&entry.name) {
+            continue;
+        }
+
+        let content = String::from_utf8_lossy(&entry.content);
+
+        let Some(key_fields) = keys::get_openssh_private_key_fields(&content) else {
+            let reason = match keys::get_key_protection(&content) {
+                keys::KeyProtection::Passphrase => "has a passphrase, run: ssh-add",
+                _ => "unsupported key format, run: ssh-add",
+            };
+            messages.push(format!("skipped  {} ({})", entry.name, reason));
+            continue;
+        };
+
+        match agent.add_identity(&key_fields, lifetime) {
+            Ok(true) => {
+                let fingerprint = keys::get_private_key_blob(incoming_entries, &entry.name)
+                    .map(|blob| keys::get_fingerprint(&blob))
+                    .unwrap_or_default();
+                messages.push(format!("added    {:<16}{}", entry.name, fingerprint));
+            }
+            _ => messages.push(format!("failed   {}", entry.name)),
+        }
+    }
+
+    Some(messages)
+}
@@ -0,0 +1,200 @@
+use std::fs;
+use std::io;
+
+use crate::constants::TOGGLE_PREV;
+use crate::utils::{self, AppPaths};
+
+/// Reads `~/.xks/.config/aliases`, one `alias = profile` per line, sorted by
+/// alias.
+pub fn read_aliases(app_paths: &AppPaths) -> Vec<(String, String)> {
+    let Ok(content) = fs::read_to_string(&app_paths.aliases_file_path) else {
+        return vec![];
+    };
+
+    let mut aliases: Vec<(String, String)> = vec![];
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let Some((alias, profile_name)) = trimmed.split_once('=') else {
+            continue;
+        };
+
+        let (alias, profile_name) = (alias.trim(), profile_name.trim());
+
+        if !alias.is_empty() && !profile_name.is_empty() {
+            aliases.retain(|(existing, _)| existing != alias);
+            aliases.push((alias.to_string(), profile_name.to_string()));
+        }
+    }
+
+    aliases.sort();
+    aliases
+}
+
+pub fn write_aliases(app_paths: &AppPaths, aliases: &[(String, String)]) -> io::Result<()> {
+    if aliases.is_empty() {
+        return match fs::remove_file(&app_paths.aliases_file_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        };
+    }
+
+    let content: String = aliases
+        .iter()
+        .map(|(alias, profile_name)| format!("{} = {}\n", alias, profile_name))
+        .collect();
+
+    utils::write_to_file(app_paths.aliases_file_path.clone(), &content)
+}
+
+pub fn find_alias(app_paths: &AppPaths, alias: &str) -> Option<String> {
+    read_aliases(app_paths)
+        .into_iter()
+        .find(|(name, _)| name == alias)
+        .map(|(_, profile_name)| profile_name)
+}
+
+/// Returns the aliases of `profile_name`.
+pub fn get_profile_aliases(aliases: &[(String, String)], profile_name: &str) -> Vec<String> {
+    aliases
+        .iter()
+        .filter(|(_, target)| target == profile_name)
+        .map(|(alias, _)| alias.clone())
+        .collect()
+}
+
+/// Points the aliases of a renamed profile at its new name, or drops them
+/// when the profile is removed.
+pub fn retarget_aliases(app_paths: &AppPaths, profile_name: &str, new_name: Option<&str>) {
+    let mut aliases = read_aliases(app_paths);
+    let count = aliases.len();
+
+    match new_name {
+        Some(new_name) => {
+            for (_, target) in aliases.iter_mut() {
+                if target == profile_name {
+                    *target = new_name.to_string();
+                }
+            }
+        }
+        None => aliases.retain(|(_, target)| target != profile_name),
+    }
+
+    if new_name.is_some() || aliases.len() != count {
+        write_aliases(app_paths, &aliases).ok();
+    }
+}
+
+/// Resolves a profile name typed by the user: a saved profile name is kept,
+/// then an alias is looked up, then, with `allow_prefix`, a unique prefix of
+/// a profile name, or of the part after `@`, is expanded. Returns every
+/// prefix match when the prefix is ambiguous, or when prefixes are not
+/// allowed, and the name unchanged when nothing matches.
+pub fn resolve_profile_name(
+    aliases: &[(String, String)],
+    profile_dirs: &[String],
+    name: &str,
+    allow_prefix: bool,
+) -> Result<String, Vec<String>> {
+    if name.is_empty() || name == TOGGLE_PREV || profile_dirs.iter().any(|dir| dir == name) {
+        return Ok(name.to_string());
+    }
+
+    if let Some((_, target)) = aliases.iter().find(|(alias, _)| alias == name) {
+        return Ok(target.clone());
+    }
+
+    let matches: Vec<String> = profile_dirs
+        .iter()
+        .filter(|dir| {
+            dir.starts_with(name)
+                || dir
+                    .split_once('@')
+                    .is_some_and(|(_, domain)| domain.starts_with(name))
+        })
+        .cloned()
+        .collect();
+
+    match matches.len() {
+        0 => Ok(name.to_string()),
+        1 if allow_prefix => Ok(matches[0].clone()),
+        _ => Err(matches),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile_dirs() -> Vec<String> {
+        ["work", "work@github.com", "workshop", "alex@gitlab.com"]
+            .map(String::from)
+            .to_vec()
+    }
+
+    fn resolve(name: &str, allow_prefix: bool) -> Result<String, Vec<String>> {
+        let aliases = vec![("w".to_string(), "workshop".to_string())];
+        resolve_profile_name(&aliases, &profile_dirs(), name, allow_prefix)
+    }
+
+    #[test]
+    fn exact_name_beats_prefix() {
+        assert_eq!(resolve("work", true), Ok("work".to_string()));
+        assert_eq!(resolve("work", false), Ok("work".to_string()));
+    }
+
+    #[test]
+    fn alias_beats_prefix() {
+        assert_eq!(resolve("w", true), Ok("workshop".to_string()));
+        assert_eq!(resolve("w", false), Ok("workshop".to_string()));
+    }
+
+    #[test]
+    fn unique_prefix_is_expanded() {
+        assert_eq!(resolve("works", true), Ok("workshop".to_string()));
+        assert_eq!(resolve("al", true), Ok("alex@gitlab.com".to_string()));
+    }
+
+    #[test]
+    fn domain_prefix_is_expanded() {
+        assert_eq!(resolve("gitl", true), Ok("alex@gitlab.com".to_string()));
+        assert_eq!(resolve("gith", true), Ok("work@github.com".to_string()));
+    }
+
+    #[test]
+    fn ambiguous_prefix_returns_every_match() {
+        assert_eq!(
+            resolve("wor", true),
+            Err(["work", "work@github.com", "workshop"]
+                .map(String::from)
+                .to_vec())
+        );
+        assert_eq!(
+            resolve("git", true),
+            Err(["work@github.com", "alex@gitlab.com"]
+                .map(String::from)
+                .to_vec())
+        );
+    }
+
+    #[test]
+    fn prefix_is_not_expanded_when_not_allowed() {
+        assert_eq!(resolve("works", false), Err(vec!["workshop".to_string()]));
+        assert_eq!(
+            resolve("gitl", false),
+            Err(vec!["alex@gitlab.com".to_string()])
+        );
+    }
+
+    #[test]
+    fn unknown_name_is_kept() {
+        assert_eq!(resolve("nobody", true), Ok("nobody".to_string()));
+        assert_eq!(resolve("nobody", false), Ok("nobody".to_string()));
+        assert_eq!(resolve(TOGGLE_PREV, false), Ok(TOGGLE_PREV.to_string()));
+    }
+}
@@ -0,0 +1,494 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+use crate::constants::{
+    APP_NAME, LAYERED_GITCONFIG_FILE_NAME, PRIVATE_KEY_MODE, PROFILE_MANIFEST_FILE_NAME,
+    SEALED_FILE_NAME, TRACKED_FILE_NAMES,
+};
+use crate::crypto::{self, MAX_PBKDF2_ITERATIONS, NONCE_LEN, PBKDF2_ITERATIONS, SALT_LEN};
+use crate::manifest;
+use crate::store::{self, FileEntry};
+use crate::utils;
+
+const ARCHIVE_FORMAT_LINE: &str = "xks-archive 2";
+/// The first entry of an archive, listing the mode and SHA-256 of every file.
+const ARCHIVE_INDEX_NAME: &str = "index";
+
+/// A file of an exported profile.
+#[derive(Debug, Clone)]
+pub struct ArchiveFile {
+    pub profile_name: String,
+    pub name: String,
+    pub mode: u32,
+    pub content: Vec<u8>,
+}
+
+impl ArchiveFile {
+    fn get_path(&self) -> String {
+        format!("{}/{}", self.profile_name, self.name)
+    }
+
+    /// The mode to write the file with: private keys and sealed files are
+    /// readable by the owner only, and no file is writable by others.
+    pub fn get_safe_mode(&self) -> u32 {
+        if utils::is_private_key_file_name(&self.name) || self.name == SEALED_FILE_NAME {
+            PRIVATE_KEY_MODE
+        } else {
+            self.mode & 0o644 | 0o600
+        }
+    }
+}
+
+/// The cleartext header of an archive.
+#[derive(Debug)]
+pub struct ArchiveHeader {
+    pub profile_names: Vec<String>,
+    /// The key derivation parameters, present when the archive is encrypted.
+    encryption: Option<(u32, Vec<u8>, Vec<u8>)>,
+}
+
+impl ArchiveHeader {
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+}
+
+fn is_profile_file_name(filename: &str) -> bool {
+    TRACKED_FILE_NAMES.contains(&filename)
+        || filename == PROFILE_MANIFEST_FILE_NAME
+        || filename == LAYERED_GITCONFIG_FILE_NAME
+        || filename == SEALED_FILE_NAME
+}
+
+/// Reads the files of a saved profile, as they are stored: sealed profiles
+/// stay sealed and layered profiles keep their manifest.
+pub fn read_profile_files(
+    data_dir_path: &Path,
+    profile_name: &str,
+) -> Result<Vec<ArchiveFile>, String> {
+    let profile_path = data_dir_path.join(profile_name);
+    let mut files: Vec<ArchiveFile> = vec![];
+
+    for filename in utils::get_files(&profile_path)? {
+        if !is_profile_file_name(&filename) {
+            continue;
+        }
+
+        let file_path = profile_path.join(&filename);
+        let content = fs::read(&file_path).map_err(|_| {
+            format!(
+                "{}: Error: Could not read file: {}",
+                APP_NAME,
+                file_path.display()
+            )
+        })?;
+
+        files.push(ArchiveFile {
+            profile_name: profile_name.to_string(),
+            mode: utils::get_mode(&file_path).unwrap_or(0o600),
+            name: filename,
+            content,
+        });
+    }
+
+    Ok(files)
+}
+
+fn encode_index(files: &[ArchiveFile]) -> Vec<u8> {
+    files
+        .iter()
+        .map(|file| {
+            format!(
+                "{:o} {:x} {}\n",
+                file.mode,
+                Sha256::digest(&file.content),
+                file.get_path()
+            )
+        })
+        .collect::<String>()
+        .into_bytes()
+}
+
+/// Encodes `files` into an archive. The header names the profiles, and with
+/// a passphrase the files are encrypted the way sealed profiles are.
+pub fn encode_archive(
+    profile_names: &[String],
+    files: &[ArchiveFile],
+    passphrase: Option<&str>,
+) -> Result<Vec<u8>, String> {
+    let mut header = format!(
+        "{}\nprofiles {}\n",
+        ARCHIVE_FORMAT_LINE,
+        profile_names.join(" ")
+    );
+
+    let mut entries = vec![FileEntry {
+        name: ARCHIVE_INDEX_NAME.to_string(),
+        content: encode_index(files),
+    }];
+    entries.extend(files.iter().map(|file| FileEntry {
+        name: file.get_path(),
+        content: file.content.clone(),
+    }));
+
+    let body = store::encode_entries(&entries);
+
+    let Some(passphrase) = passphrase else {
+        header.push('\n');
+
+        let mut data = header.into_bytes();
+        data.extend_from_slice(&body);
+        return Ok(data);
+    };
+
+    let random_err = |_| format!("{}: Error: Could not read random bytes.", APP_NAME);
+    let salt = crypto::random_bytes(SALT_LEN).map_err(random_err)?;
+    let nonce = crypto::random_bytes(NONCE_LEN).map_err(random_err)?;
+
+    header.push_str(&format!(
+        "iterations {}\nsalt {}\nnonce {}\n\n",
+        PBKDF2_ITERATIONS,
+        crypto::to_hex(&salt),
+        crypto::to_hex(&nonce)
+    ));
+
+    let key = crypto::derive_key(passphrase, &salt, PBKDF2_ITERATIONS);
+    let ciphertext = crypto::seal(&key, &nonce, header.as_bytes(), &body);
+
+    let mut data = header.into_bytes();
+    data.extend_from_slice(&ciphertext);
+
+    Ok(data)
+}
+
+fn split_archive(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let header_end = data.windows(2).position(|w| w == b"\n\n")? + 2;
+    Some(data.split_at(header_end))
+}
+
+fn parse_header(header: &[u8]) -> Option<ArchiveHeader> {
+    let header = std::str::from_utf8(header).ok()?;
+    let mut lines = header.lines();
+
+    if lines.next()? != ARCHIVE_FORMAT_LINE {
+        return None;
+    }
+
+    let mut profile_names: Vec<String> = vec![];
+    let mut iterations: u32 = 0;
+    let mut salt: Vec<u8> = vec![];
+    let mut nonce: Vec<u8> = vec![];
+
+    for line in lines {
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+
+        match key {
+            "profiles" => profile_names = value.split_whitespace().map(String::from).collect(),
+            "iterations" => iterations = value.parse().ok()?,
+            "salt" => salt = crypto::from_hex(value)?,
+            "nonce" => nonce = crypto::from_hex(value)?,
+            _ => {}
+        }
+    }
+
+    let encryption = match iterations {
+        0 => None,
+        _ if iterations > MAX_PBKDF2_ITERATIONS => return None,
+        _ if salt.len() == SALT_LEN && nonce.len() == NONCE_LEN => Some((iterations, salt, nonce)),
+        _ => return None,
+    };
+
+    Some(ArchiveHeader {
+        profile_names,
+        encryption,
+    })
+}
+
+fn corrupted_msg(archive_path: &Path) -> String {
+    format!(
+        "{}: Error: The archive is corrupted or not an {} archive: {}",
+        APP_NAME,
+        APP_NAME,
+        archive_path.display()
+    )
+}
+
+pub fn read_archive_header(data: &[u8], archive_path: &Path) -> Result<ArchiveHeader, String> {
+    split_archive(data)
+        .and_then(|(header, _)| parse_header(header))
+        .ok_or_else(|| corrupted_msg(archive_path))
+}
+
+/// Decrypts an archive when it is encrypted, and checks every file against
+/// the SHA-256 in its index.
+pub fn decode_archive(
+    data: &[u8],
+    archive_path: &Path,
+    passphrase: Option<&str>,
+) -> Result<Vec<ArchiveFile>, String> {
+    let (header_data, body) = split_archive(data).ok_or_else(|| corrupted_msg(archive_path))?;
+    let header = parse_header(header_data).ok_or_else(|| corrupted_msg(archive_path))?;
+
+    let mut body = body.to_vec();
+
+    if let Some((iterations, salt, nonce)) = &header.encryption {
+        let passphrase = passphrase.unwrap_or_default();
+        let key = crypto::derive_key(passphrase, salt, *iterations);
+
+        body = crypto::open(&key, nonce, header_data, &body).ok_or_else(|| {
+            format!(
+                "{}: Error: Wrong passphrase, or the archive has been tampered with.",
+                APP_NAME
+            )
+        })?;
+    }
+
+    let mut entries = store::decode_entries(&body).ok_or_else(|| corrupted_msg(archive_path))?;
+
+    if entries.first().map(|entry| entry.name.as_str()) != Some(ARCHIVE_INDEX_NAME) {
+        return Err(corrupted_msg(archive_path));
+    }
+
+    let index =
+        String::from_utf8(entries.remove(0).content).map_err(|_| corrupted_msg(archive_path))?;
+    let mut files: Vec<ArchiveFile> = vec![];
+
+    for line in index.lines() {
+        let mut fields = line.splitn(3, ' ');
+        let (Some(mode), Some(hash), Some(path)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(corrupted_msg(archive_path));
+        };
+
+        let Some((profile_name, name)) = path.split_once('/') else {
+            return Err(corrupted_msg(archive_path));
+        };
+
+        if !utils::is_safe_profile_name(profile_name)
+            || !is_profile_file_name(name)
+            || !header
+                .profile_names
+                .iter()
+                .any(|listed| listed == profile_name)
+        {
+            return Err(format!(
+                "{}: Error: The archive holds an unexpected file: {}",
+                APP_NAME, path
+            ));
+        }
+
+        let Some(entry) = entries.iter().find(|entry| entry.name == path) else {
+            return Err(format!(
+                "{}: Error: The archive is missing a file: {}",
+                APP_NAME, path
+            ));
+        };
+
+        if format!("{:x}", Sha256::digest(&entry.content)) != hash {
+            return Err(format!(
+                "{}: Error: The file {} does not match its hash in the archive.",
+                APP_NAME, path
+            ));
+        }
+
+        // the base of a layered profile names a directory of `~/.xks` too
+        if name == PROFILE_MANIFEST_FILE_NAME
+            && let Some(base) =
+                manifest::parse_manifest(&String::from_utf8_lossy(&entry.content)).base
+            && !utils::is_safe_profile_name(&base)
+        {
+            return Err(format!(
+                "{}: Error: The profile {:?} in the archive has an invalid base profile: {:?}",
+                APP_NAME, profile_name, base
+            ));
+        }
+
+        files.push(ArchiveFile {
+            profile_name: profile_name.to_string(),
+            name: name.to_string(),
+            mode: u32::from_str_radix(mode, 8).map_err(|_| corrupted_msg(archive_path))?,
+            content: entry.content.clone(),
+        });
+    }
+
+    if files.len() != entries.len()
+        || header
+            .profile_names
+            .iter()
+            .any(|profile_name| !utils::is_safe_profile_name(profile_name))
+    {
+        return Err(corrupted_msg(archive_path));
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::GITCONFIG_FILE_NAME;
+
+    fn file(profile_name: &str, name: &str, content: &[u8]) -> ArchiveFile {
+        ArchiveFile {
+            profile_name: profile_name.to_string(),
+            name: name.to_string(),
+            mode: 0o644,
+            content: content.to_vec(),
+        }
+    }
+
+    fn files() -> Vec<ArchiveFile> {
+        vec![
+            file("work", GITCONFIG_FILE_NAME, b"[user]\n\tname = Alex\n"),
+            ArchiveFile {
+                mode: 0o600,
+                ..file("work", "id_ed25519", b"private key")
+            },
+            file("home", GITCONFIG_FILE_NAME, b"[user]\n\tname = Lex\n"),
+        ]
+    }
+
+    fn profile_names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// Builds a plain archive by hand, with any index and entries.
+    fn raw_archive(profiles: &str, index: &str, entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut all_entries = vec![FileEntry {
+            name: ARCHIVE_INDEX_NAME.to_string(),
+            content: index.as_bytes().to_vec(),
+        }];
+        all_entries.extend(entries.iter().map(|(name, content)| FileEntry {
+            name: name.to_string(),
+            content: content.to_vec(),
+        }));
+
+        let mut data = format!("{}\nprofiles {}\n\n", ARCHIVE_FORMAT_LINE, profiles).into_bytes();
+        data.extend_from_slice(&store::encode_entries(&all_entries));
+        data
+    }
+
+    fn index_line(path: &str, content: &[u8]) -> String {
+        format!("644 {:x} {}\n", Sha256::digest(content), path)
+    }
+
+    fn decode(data: &[u8], passphrase: Option<&str>) -> Result<Vec<ArchiveFile>, String> {
+        decode_archive(data, Path::new("test.xks"), passphrase)
+    }
+
+    fn assert_same_files(decoded: &[ArchiveFile], expected: &[ArchiveFile]) {
+        let fields = |files: &[ArchiveFile]| -> Vec<(String, String, u32, Vec<u8>)> {
+            files
+                .iter()
+                .map(|file| {
+                    (
+                        file.profile_name.clone(),
+                        file.name.clone(),
+                        file.mode,
+                        file.content.clone(),
+                    )
+                })
+                .collect()
+        };
+
+        assert_eq!(fields(decoded), fields(expected));
+    }
+
+    #[test]
+    fn plain_archive_round_trips() {
+        let data = encode_archive(&profile_names(&["work", "home"]), &files(), None).unwrap();
+
+        let header = read_archive_header(&data, Path::new("test.xks")).unwrap();
+        assert_eq!(header.profile_names, ["work", "home"]);
+        assert!(!header.is_encrypted());
+
+        assert_same_files(&decode(&data, None).unwrap(), &files());
+    }
+
+    #[test]
+    fn encrypted_archive_round_trips() {
+        let data =
+            encode_archive(&profile_names(&["work", "home"]), &files(), Some("pass")).unwrap();
+
+        assert!(
+            read_archive_header(&data, Path::new("test.xks"))
+                .unwrap()
+                .is_encrypted()
+        );
+        assert!(!data.windows(11).any(|window| window == b"private key"));
+
+        assert_same_files(&decode(&data, Some("pass")).unwrap(), &files());
+    }
+
+    #[test]
+    fn encrypted_archive_rejects_wrong_passphrase() {
+        let data =
+            encode_archive(&profile_names(&["work", "home"]), &files(), Some("pass")).unwrap();
+
+        let err = decode(&data, Some("wrong")).unwrap_err();
+        assert!(err.contains("Wrong passphrase"), "{}", err);
+
+        let err = decode(&data, None).unwrap_err();
+        assert!(err.contains("Wrong passphrase"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_hash_mismatch() {
+        let path = "work/.gitconfig";
+        let data = raw_archive(
+            "work",
+            &index_line(path, b"[user]\n\tname = Alex\n"),
+            &[(path, b"[user]\n\tname = Eve\n")],
+        );
+
+        let err = decode(&data, None).unwrap_err();
+        assert!(err.contains("does not match its hash"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_unsafe_profile_names() {
+        for (profiles, path) in [
+            ("..", "../.gitconfig"),
+            (".hidden", ".hidden/.gitconfig"),
+            ("work", "work/../.gitconfig"),
+        ] {
+            let data = raw_archive(profiles, &index_line(path, b""), &[(path, b"")]);
+
+            let err = decode(&data, None).unwrap_err();
+            assert!(err.contains("unexpected file"), "{}: {}", path, err);
+        }
+    }
+
+    #[test]
+    fn rejects_unlisted_profile() {
+        let path = "home/.gitconfig";
+        let data = raw_archive("work", &index_line(path, b""), &[(path, b"")]);
+
+        let err = decode(&data, None).unwrap_err();
+        assert!(err.contains("unexpected file"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_unindexed_entry() {
+        let path = "work/.gitconfig";
+        let data = raw_archive(
+            "work",
+            &index_line(path, b""),
+            &[(path, b""), ("work/id_rsa", b"extra key")],
+        );
+
+        let err = decode(&data, None).unwrap_err();
+        assert!(err.contains("corrupted"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_unsafe_base_profile() {
+        let path = format!("work/{}", PROFILE_MANIFEST_FILE_NAME);
+        let manifest = b"base = ../../etc\n";
+        let data = raw_archive("work", &index_line(&path, manifest), &[(&path, manifest)]);
+
+        let err = decode(&data, None).unwrap_err();
+        assert!(err.contains("invalid base profile"), "{}", err);
+    }
+}
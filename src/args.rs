@@ -0,0 +1,747 @@
+use crate::constants::{APP_NAME, HELP_LINE, TOGGLE_PREV};
+use crate::history;
+
+pub const YES_OPTION: &str = "yes";
+pub const JSON_OPTION: &str = "json";
+pub const HELP_OPTION: &str = "help";
+pub const IS_OPTION: &str = "is";
+pub const DRY_RUN_OPTION: &str = "dry-run";
+pub const WAIT_OPTION: &str = "wait";
+pub const SINCE_OPTION: &str = "since";
+pub const OLDER_THAN_OPTION: &str = "older-than";
+pub const ALL_OPTION: &str = "all";
+pub const OUTPUT_OPTION: &str = "output";
+pub const ENCRYPT_OPTION: &str = "encrypt";
+pub const RENAME_OPTION: &str = "rename";
+pub const OVERWRITE_OPTION: &str = "overwrite";
+pub const SKIP_OPTION: &str = "skip";
+pub const DECRYPT_OPTION: &str = "decrypt";
+
+/// An option such as `-y`, `--json` or `--is <profile>`.
+#[derive(Debug)]
+pub struct OptionSpec {
+    pub name: &'static str,
+    pub short: Option<char>,
+    pub value_name: Option<&'static str>,
+    pub help: &'static str,
+}
+
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub arguments: &'static [&'static str],
+    pub summary: &'static str,
+    pub options: &'static [OptionSpec],
+}
+
+const YES: OptionSpec = OptionSpec {
+    name: YES_OPTION,
+    short: Some('y'),
+    value_name: None,
+    help: "Skip confirmation prompts",
+};
+
+const JSON: OptionSpec = OptionSpec {
+    name: JSON_OPTION,
+    short: None,
+    value_name: None,
+    help: "Print the result as a JSON object",
+};
+
+const DRY_RUN: OptionSpec = OptionSpec {
+    name: DRY_RUN_OPTION,
+    short: None,
+    value_name: None,
+    help: "Print the files that would change, and change nothing",
+};
+
+/// Commands with this option change files, and hold the lock on `~/.xks`.
+const WAIT: OptionSpec = OptionSpec {
+    name: WAIT_OPTION,
+    short: None,
+    value_name: None,
+    help: "Wait for another running xks to finish, instead of failing",
+};
+
+const HELP: OptionSpec = OptionSpec {
+    name: HELP_OPTION,
+    short: Some('h'),
+    value_name: None,
+    help: "Show the help of the command",
+};
+
+/// Accepted by every command, anywhere on the command line.
+const GLOBAL_OPTIONS: &[OptionSpec] = &[YES, HELP];
+
+pub const LIST_COMMAND: CommandSpec = CommandSpec {
+    name: "list",
+    aliases: &[],
+    arguments: &[],
+    summary: "List saved profiles and current_files state",
+    options: &[JSON],
+};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    LIST_COMMAND,
+    CommandSpec {
+        name: "save",
+        aliases: &[],
+        arguments: &["<profile>"],
+        summary: "Save current_files as a profile",
+        options: &[DRY_RUN, JSON, WAIT],
+    },
+    CommandSpec {
+        name: "use",
+        aliases: &[TOGGLE_PREV],
+        arguments: &["[<profile>]"],
+        summary: "Apply a saved profile, or go back with `-`, `-N` or `@{<time>}`",
+        options: &[DRY_RUN, JSON, WAIT],
+    },
+    CommandSpec {
+        name: "remove",
+        aliases: &["delete"],
+        arguments: &["<profile>"],
+        summary: "Move a saved profile to the trash",
+        options: &[DRY_RUN, JSON, WAIT],
+    },
+    CommandSpec {
+        name: "rename",
+        aliases: &[],
+        arguments: &["<old>", "<new>"],
+        summary: "Rename a saved profile",
+        options: &[WAIT],
+    },
+    CommandSpec {
+        name: "copy",
+        aliases: &[],
+        arguments: &["<src>", "<dst>"],
+        summary: "Copy a saved profile to a new name",
+        options: &[WAIT],
+    },
+    CommandSpec {
+        name: "export",
+        aliases: &[],
+        arguments: &["[<profile>]"],
+        summary: "Write saved profiles to one archive, to copy them to another machine",
+        options: &[
+            OptionSpec {
+                name: ALL_OPTION,
+                short: None,
+                value_name: None,
+                help: "Export every saved profile",
+            },
+            OptionSpec {
+                name: OUTPUT_OPTION,
+                short: Some('o'),
+                value_name: Some("<file>"),
+                help: "Write the archive to this file",
+            },
+            OptionSpec {
+                name: ENCRYPT_OPTION,
+                short: None,
+                value_name: None,
+                help: "Encrypt the archive with a passphrase",
+            },
+        ],
+    },
+    CommandSpec {
+        name: "import-archive",
+        aliases: &[],
+        arguments: &["<file>"],
+        summary: "Add the profiles of an archive written by export",
+        options: &[
+            OptionSpec {
+                name: RENAME_OPTION,
+                short: None,
+                value_name: None,
+                help: "Import profiles that already exist under a new name",
+            },
+            OptionSpec {
+                name: OVERWRITE_OPTION,
+                short: None,
+                value_name: None,
+                help: "Replace profiles that already exist, moving them to the trash",
+            },
+            OptionSpec {
+                name: SKIP_OPTION,
+                short: None,
+                value_name: None,
+                help: "Leave out profiles that already exist",
+            },
+            WAIT,
+        ],
+    },
+    CommandSpec {
+        name: "history",
+        aliases: &[],
+        arguments: &[],
+        summary: "List the profile switches, most recent first",
+        options: &[JSON],
+    },
+    CommandSpec {
+        name: "log",
+        aliases: &[],
+        arguments: &[],
+        summary: "List the audit log of use, save, remove and discard, most recent first",
+        options: &[
+            OptionSpec {
+                name: SINCE_OPTION,
+                short: None,
+                value_name: Some("<time>"),
+                help: "Only list entries since a date like 2024-05-01, or an age like 2.days.ago",
+            },
+            JSON,
+        ],
+    },
+    CommandSpec {
+        name: "trash",
+        aliases: &[],
+        arguments: &["[<action>]", "[<trashed>]"],
+        summary: "List, restore or empty the removed profiles",
+        options: &[
+            OptionSpec {
+                name: OLDER_THAN_OPTION,
+                short: None,
+                value_name: Some("<age>"),
+                help: "Only empty the profiles removed longer ago, e.g. 30d or 2.weeks",
+            },
+            WAIT,
+        ],
+    },
+    CommandSpec {
+        name: "show",
+        aliases: &[],
+        arguments: &["<profile>"],
+        summary: "Show the files, identity, keys and hosts of a profile",
+        options: &[JSON],
+    },
+    CommandSpec {
+        name: "diff",
+        aliases: &[],
+        arguments: &["<profile>", "[<other>]"],
+        summary: "Compare a profile with current_files, or with another profile",
+        options: &[OptionSpec {
+            name: DECRYPT_OPTION,
+            short: None,
+            value_name: None,
+            help: "Decrypt sealed profiles to compare their files",
+        }],
+    },
+    CommandSpec {
+        name: "inherit",
+        aliases: &[],
+        arguments: &["<profile>", "[<base>]"],
+        summary: "Layer a profile's .gitconfig on a base profile",
+        options: &[WAIT],
+    },
+    CommandSpec {
+        name: "discard",
+        aliases: &[],
+        arguments: &[],
+        summary: "Delete current_files",
+        options: &[DRY_RUN, JSON, WAIT],
+    },
+    CommandSpec {
+        name: "status",
+        aliases: &[],
+        arguments: &[],
+        summary: "Print nothing, exit with 0 when a profile is in use and 8 otherwise",
+        options: &[OptionSpec {
+            name: IS_OPTION,
+            short: None,
+            value_name: Some("<profile>"),
+            help: "Check this profile instead of any saved profile",
+        }],
+    },
+    CommandSpec {
+        name: "seal",
+        aliases: &[],
+        arguments: &["<profile>"],
+        summary: "Encrypt a saved profile with a passphrase",
+        options: &[WAIT],
+    },
+    CommandSpec {
+        name: "unseal",
+        aliases: &[],
+        arguments: &["<profile>"],
+        summary: "Decrypt a sealed profile back to plain files",
+        options: &[WAIT],
+    },
+    CommandSpec {
+        name: "audit",
+        aliases: &[],
+        arguments: &[],
+        summary: "Report private keys saved without a passphrase",
+        options: &[],
+    },
+    CommandSpec {
+        name: "repair",
+        aliases: &[],
+        arguments: &[],
+        summary: "Restrict permissions of keys and data directories",
+        options: &[WAIT],
+    },
+    CommandSpec {
+        name: "alias",
+        aliases: &[],
+        arguments: &["[<action>]", "[<alias>]", "[<profile>]"],
+        summary: "List aliases, or set and remove a short name for a profile",
+        options: &[WAIT],
+    },
+    CommandSpec {
+        name: "tui",
+        aliases: &[],
+        arguments: &[],
+        summary: "Browse profiles in a full-screen terminal UI",
+        options: &[WAIT],
+    },
+    CommandSpec {
+        name: "completions",
+        aliases: &[],
+        arguments: &["<shell>"],
+        summary: "Print the completion script for bash, zsh or fish",
+        options: &[],
+    },
+    CommandSpec {
+        name: "version",
+        aliases: &["--version", "-v"],
+        arguments: &[],
+        summary: "Show version number",
+        options: &[JSON],
+    },
+    CommandSpec {
+        name: "help",
+        aliases: &["--help", "-h"],
+        arguments: &["[<command>]"],
+        summary: "Show the help message, or the help of a command",
+        options: &[],
+    },
+];
+
+impl CommandSpec {
+    pub fn has_option(&self, name: &str) -> bool {
+        self.options.iter().any(|option| option.name == name)
+    }
+}
+
+/// The command line split into a command, its options and its positional
+/// arguments.
+#[derive(Debug)]
+pub struct ParsedArgs {
+    pub command: &'static CommandSpec,
+    pub positionals: Vec<String>,
+    options: Vec<(&'static str, Option<String>)>,
+}
+
+impl ParsedArgs {
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(option, _)| *option == name)
+    }
+
+    /// Returns the value of the last occurrence of an option.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(option, _)| *option == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    /// Returns a positional argument, or an empty string when it is missing.
+    pub fn positional(&self, index: usize) -> &str {
+        self.positionals
+            .get(index)
+            .map(|s| s.as_str())
+            .unwrap_or_default()
+    }
+}
+
+pub fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    if history::is_history_step(name) {
+        return find_command(TOGGLE_PREV);
+    }
+
+    COMMANDS
+        .iter()
+        .find(|command| command.name == name || command.aliases.contains(&name))
+}
+
+/// Finds an option of `command`. Before the command is known, the options of
+/// every command are searched, and checked once the command is parsed.
+fn find_option(
+    command: Option<&'static CommandSpec>,
+    matches: impl Fn(&OptionSpec) -> bool,
+) -> Option<&'static OptionSpec> {
+    let command_options: Vec<&'static OptionSpec> = match command {
+        Some(command) => command.options.iter().collect(),
+        None => COMMANDS
+            .iter()
+            .flat_map(|command| command.options.iter())
+            .collect(),
+    };
+
+    GLOBAL_OPTIONS
+        .iter()
+        .chain(command_options)
+        .find(|option| matches(option))
+}
+
+/// Returns the global options followed by the options of `command`.
+pub fn get_command_options(command: Option<&CommandSpec>) -> Vec<&'static OptionSpec> {
+    let command_options: &'static [OptionSpec] = match command {
+        Some(command) => command.options,
+        None => &[],
+    };
+
+    GLOBAL_OPTIONS.iter().chain(command_options).collect()
+}
+
+fn usage_error(message: String) -> String {
+    format!("{}: {}\n\n{}", APP_NAME, message, HELP_LINE)
+}
+
+/// The error for an option `command` does not have, naming the commands that
+/// have it, since options such as `--json` only apply to some commands.
+fn unknown_option_error(arg: &str, command: &CommandSpec) -> String {
+    let long_name = arg
+        .strip_prefix("--")
+        .map(|long| long.split_once('=').map_or(long, |(name, _)| name));
+    let short = arg.strip_prefix('-').and_then(|short| {
+        let mut chars = short.chars();
+        chars.next().filter(|_| chars.next().is_none())
+    });
+
+    let command_names: Vec<&str> = COMMANDS
+        .iter()
+        .filter(|other| {
+            other.options.iter().any(|option| match long_name {
+                Some(name) => option.name == name,
+                None => short.is_some() && option.short == short,
+            })
+        })
+        .map(|other| other.name)
+        .collect();
+
+    if command_names.is_empty() {
+        return usage_error(format!("Unknown option {:?} for {:?}.", arg, command.name));
+    }
+
+    usage_error(format!(
+        "Option {:?} is not supported by {:?}, only by: {}.",
+        arg,
+        command.name,
+        command_names.join(", ")
+    ))
+}
+
+/// Parses the arguments after the program name. Options may come before or
+/// after the command, `--name=value` and `--name value` are both accepted, and
+/// everything after `--` is positional. When an option is repeated, the last
+/// value wins.
+pub fn parse(args: &[String]) -> Result<ParsedArgs, String> {
+    let mut command: Option<&'static CommandSpec> = None;
+    let mut positionals: Vec<String> = vec![];
+    let mut options: Vec<(&'static str, Option<String>)> = vec![];
+    let mut only_positionals = false;
+    let mut index = 0;
+
+    while index < args.len() {
+        let arg = args[index].as_str();
+        index += 1;
+
+        let is_history_step = history::is_history_step(arg);
+
+        if only_positionals || arg == TOGGLE_PREV || is_history_step || !arg.starts_with('-') {
+            if command.is_none() && !only_positionals {
+                command = Some(
+                    find_command(arg)
+                        .ok_or_else(|| usage_error(format!("Unrecognized command {:?}.", arg)))?,
+                );
+
+                // `xks -` and `xks -2` are short for `xks use -` and `xks use -2`
+                if arg == TOGGLE_PREV || is_history_step {
+                    positionals.push(arg.to_string());
+                }
+            } else {
+                positionals.push(arg.to_string());
+            }
+            continue;
+        }
+
+        if arg == "--" {
+            only_positionals = true;
+            continue;
+        }
+
+        // `--version`, `-v`, `--help` and `-h` also work as commands
+        if command.is_none()
+            && let Some(alias_command) = find_command(arg)
+        {
+            command = Some(alias_command);
+            continue;
+        }
+
+        let (spec, inline_value) = match arg.strip_prefix("--") {
+            Some(long) => {
+                let (name, inline_value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let spec = find_option(command, |option| option.name == name);
+                (spec, inline_value)
+            }
+            None => {
+                let mut chars = arg[1..].chars();
+                let spec = match (chars.next(), chars.next()) {
+                    (Some(short), None) => {
+                        find_option(command, |option| option.short == Some(short))
+                    }
+                    _ => None,
+                };
+                (spec, None)
+            }
+        };
+
+        let Some(spec) = spec else {
+            return Err(match command {
+                Some(command) => unknown_option_error(arg, command),
+                None => usage_error(format!("Unknown option {:?}.", arg)),
+            });
+        };
+
+        let value = match (spec.value_name, inline_value) {
+            (None, None) => None,
+            (None, Some(_)) => {
+                return Err(usage_error(format!(
+                    "Option --{} does not take a value.",
+                    spec.name
+                )));
+            }
+            (Some(_), Some(value)) => Some(value),
+            // the next argument is the value even when it starts with `-`
+            (Some(value_name), None) => match args.get(index) {
+                Some(value) => {
+                    index += 1;
+                    Some(value.clone())
+                }
+                None => {
+                    return Err(usage_error(format!(
+                        "Option --{} requires a value: {}",
+                        spec.name, value_name
+                    )));
+                }
+            },
+        };
+
+        options.push((spec.name, value));
+    }
+
+    let command = command.unwrap_or(&LIST_COMMAND);
+
+    if positionals.len() > command.arguments.len() {
+        return Err(usage_error(format!(
+            "Too many arguments provided for {:?}.",
+            command.name
+        )));
+    }
+
+    // options given before the command are checked against it now
+    for (name, _) in &options {
+        if find_option(Some(command), |option| option.name == *name).is_none() {
+            return Err(unknown_option_error(&format!("--{}", name), command));
+        }
+    }
+
+    Ok(ParsedArgs {
+        command,
+        positionals,
+        options,
+    })
+}
+
+/// Builds the help of a command from its spec.
+pub fn get_command_help(command: &CommandSpec) -> String {
+    let mut usage = format!("{} {}", APP_NAME, command.name);
+    for argument in command.arguments {
+        usage.push(' ');
+        usage.push_str(argument);
+    }
+    usage.push_str(" [options]");
+
+    let mut lines = vec![
+        format!("Usage:\n    {}", usage),
+        String::new(),
+        command.summary.to_string(),
+    ];
+
+    if !command.aliases.is_empty() {
+        lines.push(format!("Aliases: {}", command.aliases.join(", ")));
+    }
+
+    lines.push(String::new());
+    lines.push("Options:".to_string());
+
+    for option in command.options.iter().chain(GLOBAL_OPTIONS) {
+        let mut label = match option.short {
+            Some(short) => format!("-{}, --{}", short, option.name),
+            None => format!("--{}", option.name),
+        };
+        if let Some(value_name) = option.value_name {
+            label.push(' ');
+            label.push_str(value_name);
+        }
+
+        lines.push(format!("    {:<22} {}", label, option.help));
+    }
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<ParsedArgs, String> {
+        parse(
+            &args
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect::<Vec<String>>(),
+        )
+    }
+
+    #[test]
+    fn yes_is_accepted_in_any_position() {
+        for args in [
+            ["-y", "remove", "alex"],
+            ["remove", "-y", "alex"],
+            ["remove", "alex", "-y"],
+        ] {
+            let parsed = parse_args(&args).unwrap();
+            assert_eq!(parsed.command.name, "remove");
+            assert_eq!(parsed.positionals, ["alex"]);
+            assert!(parsed.flag(YES_OPTION));
+        }
+
+        assert!(
+            parse_args(&["remove", "alex", "--yes"])
+                .unwrap()
+                .flag(YES_OPTION)
+        );
+    }
+
+    #[test]
+    fn bare_dash_and_history_steps_are_use() {
+        let parsed = parse_args(&["-"]).unwrap();
+        assert_eq!(parsed.command.name, "use");
+        assert_eq!(parsed.positionals, ["-"]);
+
+        let parsed = parse_args(&["-2"]).unwrap();
+        assert_eq!(parsed.command.name, "use");
+        assert_eq!(parsed.positionals, ["-2"]);
+
+        let parsed = parse_args(&["use", "-2", "--dry-run"]).unwrap();
+        assert_eq!(parsed.positionals, ["-2"]);
+        assert!(parsed.flag(DRY_RUN_OPTION));
+    }
+
+    #[test]
+    fn option_values_are_inline_or_next() {
+        let parsed = parse_args(&["status", "--is=alex"]).unwrap();
+        assert_eq!(parsed.value(IS_OPTION), Some("alex"));
+
+        let parsed = parse_args(&["status", "--is", "alex"]).unwrap();
+        assert_eq!(parsed.value(IS_OPTION), Some("alex"));
+        assert!(parsed.positionals.is_empty());
+
+        let parsed = parse_args(&["export", "--all", "-o", "profiles.xks"]).unwrap();
+        assert_eq!(parsed.value(OUTPUT_OPTION), Some("profiles.xks"));
+    }
+
+    #[test]
+    fn option_values_may_start_with_a_dash() {
+        let parsed = parse_args(&["export", "alex", "-o", "-backup.xks"]).unwrap();
+        assert_eq!(parsed.value(OUTPUT_OPTION), Some("-backup.xks"));
+        assert_eq!(parsed.positionals, ["alex"]);
+
+        let parsed = parse_args(&["status", "--is", "-"]).unwrap();
+        assert_eq!(parsed.value(IS_OPTION), Some("-"));
+    }
+
+    #[test]
+    fn missing_and_unexpected_values_are_rejected() {
+        let err = parse_args(&["status", "--is"]).unwrap_err();
+        assert!(err.contains("Option --is requires a value"));
+
+        let err = parse_args(&["list", "--json=yes"]).unwrap_err();
+        assert!(err.contains("Option --json does not take a value"));
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let parsed = parse_args(&["show", "--", "-alex"]).unwrap();
+        assert_eq!(parsed.command.name, "show");
+        assert_eq!(parsed.positionals, ["-alex"]);
+
+        let parsed = parse_args(&["rename", "--", "-a", "--json"]).unwrap();
+        assert_eq!(parsed.positionals, ["-a", "--json"]);
+        assert!(!parsed.flag(JSON_OPTION));
+    }
+
+    #[test]
+    fn unknown_options_are_rejected() {
+        let err = parse_args(&["list", "--nope"]).unwrap_err();
+        assert!(err.contains("Unknown option \"--nope\" for \"list\""));
+
+        let err = parse_args(&["--nope"]).unwrap_err();
+        assert!(err.contains("Unknown option \"--nope\"."));
+
+        let err = parse_args(&["list", "-x"]).unwrap_err();
+        assert!(err.contains("Unknown option \"-x\""));
+
+        // an option of another command, given before the command
+        let err = parse_args(&["--dry-run", "show", "alex"]).unwrap_err();
+        assert!(err.contains(
+            "Option \"--dry-run\" is not supported by \"show\", only by: save, use, remove, discard."
+        ));
+    }
+
+    #[test]
+    fn json_is_refused_by_commands_without_json_output() {
+        for args in [
+            ["rename", "alex", "alex_2", "--json"],
+            ["--json", "rename", "alex", "alex_2"],
+        ] {
+            let err = parse_args(&args).unwrap_err();
+            assert!(err.contains(
+                "Option \"--json\" is not supported by \"rename\", only by: list, save, use, \
+                 remove, history, log, show, discard, version."
+            ));
+        }
+
+        let err = parse_args(&["export", "alex", "-o", "a.xks", "-y", "-x"]).unwrap_err();
+        assert!(err.contains("Unknown option \"-x\" for \"export\"."));
+
+        let err = parse_args(&["list", "-o", "a.xks"]).unwrap_err();
+        assert!(err.contains("Option \"-o\" is not supported by \"list\", only by: export."));
+    }
+
+    #[test]
+    fn repeated_options_keep_the_last_value() {
+        let parsed = parse_args(&["status", "--is", "alex", "--is=work"]).unwrap();
+        assert_eq!(parsed.value(IS_OPTION), Some("work"));
+
+        let parsed = parse_args(&["-y", "remove", "alex", "-y"]).unwrap();
+        assert!(parsed.flag(YES_OPTION));
+    }
+
+    #[test]
+    fn no_command_is_list() {
+        let parsed = parse_args(&[]).unwrap();
+        assert_eq!(parsed.command.name, "list");
+
+        let err = parse_args(&["nope"]).unwrap_err();
+        assert!(err.contains("Unrecognized command \"nope\"."));
+    }
+}
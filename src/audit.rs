@@ -0,0 +1,285 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::json::Json;
+use crate::settings::Settings;
+use crate::utils::{self, AppPaths};
+
+/// A line of the audit log: a command that changed the current files or the
+/// saved profiles, and the identity in use after it.
+#[derive(Debug, Default, PartialEq)]
+pub struct AuditEntry {
+    pub timestamp: u64,
+    pub command: String,
+    pub profile: String,
+    /// The profiles in use before the command.
+    pub from: Vec<String>,
+    /// The profiles in use after the command.
+    pub to: Vec<String>,
+    pub email: String,
+    /// The SSH fingerprint of every current private key.
+    pub keys: Vec<(String, String)>,
+    /// The SHA-256 of every current file.
+    pub files: Vec<(String, String)>,
+    pub user: String,
+    pub host: String,
+}
+
+// values are written bare when they can be, and quoted otherwise
+fn format_value(value: &str) -> String {
+    if !value.is_empty() && !value.contains([' ', '"', '=', '\\']) {
+        return value.to_string();
+    }
+
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn format_pairs(pairs: &[(String, String)]) -> String {
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .filter(|item| !item.is_empty())
+        .map(|item| item.to_string())
+        .collect()
+}
+
+fn parse_pairs(value: &str) -> Vec<(String, String)> {
+    parse_list(value)
+        .into_iter()
+        .filter_map(|item| {
+            let (name, value) = item.split_once('=')?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Splits a logfmt line into its `key=value` fields.
+fn parse_fields(line: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = vec![];
+    let mut chars = line.trim().chars().peekable();
+
+    while chars.peek().is_some() {
+        let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        let mut value = String::new();
+
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+        }
+
+        value.extend(chars.by_ref().take_while(|c| *c != ' '));
+        fields.push((key.trim().to_string(), value));
+    }
+
+    fields
+}
+
+impl AuditEntry {
+    pub fn to_line(&self) -> String {
+        [
+            ("time", self.timestamp.to_string()),
+            ("command", self.command.clone()),
+            ("profile", self.profile.clone()),
+            ("from", self.from.join(",")),
+            ("to", self.to.join(",")),
+            ("email", self.email.clone()),
+            ("keys", format_pairs(&self.keys)),
+            ("files", format_pairs(&self.files)),
+            ("user", self.user.clone()),
+            ("host", self.host.clone()),
+        ]
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, format_value(value)))
+        .collect::<Vec<String>>()
+        .join(" ")
+    }
+
+    pub fn parse(line: &str) -> Option<AuditEntry> {
+        let mut entry = AuditEntry::default();
+
+        for (key, value) in parse_fields(line) {
+            match key.as_str() {
+                "time" => entry.timestamp = value.parse().ok()?,
+                "command" => entry.command = value,
+                "profile" => entry.profile = value,
+                "from" => entry.from = parse_list(&value),
+                "to" => entry.to = parse_list(&value),
+                "email" => entry.email = value,
+                "keys" => entry.keys = parse_pairs(&value),
+                "files" => entry.files = parse_pairs(&value),
+                "user" => entry.user = value,
+                "host" => entry.host = value,
+                _ => {}
+            }
+        }
+
+        (!entry.command.is_empty()).then_some(entry)
+    }
+
+    pub fn to_json(&self) -> Json {
+        let pairs_json = |pairs: &[(String, String)], value_key: &str| -> Json {
+            Json::Array(
+                pairs
+                    .iter()
+                    .map(|(name, value)| {
+                        Json::Object(vec![
+                            ("name".to_string(), Json::from(name)),
+                            (value_key.to_string(), Json::from(value)),
+                        ])
+                    })
+                    .collect(),
+            )
+        };
+
+        Json::object([
+            ("timestamp", Json::from(self.timestamp)),
+            ("command", Json::from(&self.command)),
+            ("profile", Json::from(&self.profile)),
+            ("from", Json::from(self.from.clone())),
+            ("to", Json::from(self.to.clone())),
+            ("email", Json::from(&self.email)),
+            ("keys", pairs_json(&self.keys, "fingerprint")),
+            ("files", pairs_json(&self.files, "sha256")),
+            ("user", Json::from(&self.user)),
+            ("host", Json::from(&self.host)),
+        ])
+    }
+}
+
+pub fn get_user() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("LOGNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+pub fn get_host() -> String {
+    env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn get_rotated_path(app_paths: &AppPaths, index: usize) -> PathBuf {
+    let mut path = app_paths.audit_log_file_path.clone().into_os_string();
+    path.push(format!(".{}", index));
+    PathBuf::from(path)
+}
+
+/// Moves `audit.log` to `audit.log.1`, shifting the older logs up and
+/// deleting the ones past `audit_log_keep`.
+fn rotate(app_paths: &AppPaths, keep: usize) -> io::Result<()> {
+    if keep == 0 {
+        return fs::remove_file(&app_paths.audit_log_file_path);
+    }
+
+    for index in (1..keep).rev() {
+        let rotated_path = get_rotated_path(app_paths, index);
+
+        if rotated_path.exists() {
+            fs::rename(&rotated_path, get_rotated_path(app_paths, index + 1))?;
+        }
+    }
+
+    fs::rename(
+        &app_paths.audit_log_file_path,
+        get_rotated_path(app_paths, 1),
+    )
+}
+
+/// Appends `entry` to the audit log, rotating it first when the line would
+/// make it larger than `audit_log_max_size`.
+pub fn append(app_paths: &AppPaths, settings: &Settings, entry: &AuditEntry) -> io::Result<()> {
+    let line = format!("{}\n", entry.to_line());
+
+    if let Ok(metadata) = fs::metadata(&app_paths.audit_log_file_path)
+        && metadata.len() > 0
+        && metadata.len() + line.len() as u64 > settings.audit_log_max_size
+    {
+        rotate(app_paths, settings.audit_log_keep)?;
+    }
+
+    utils::append_to_private_file(&app_paths.audit_log_file_path, &line)
+}
+
+/// Reads the audit log and its rotated files, oldest entry first.
+pub fn read_entries(app_paths: &AppPaths, settings: &Settings) -> Vec<AuditEntry> {
+    let mut paths: Vec<PathBuf> = (1..=settings.audit_log_keep)
+        .rev()
+        .map(|index| get_rotated_path(app_paths, index))
+        .collect();
+    paths.push(app_paths.audit_log_file_path.clone());
+
+    paths
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|content| {
+            content
+                .lines()
+                .filter_map(AuditEntry::parse)
+                .collect::<Vec<AuditEntry>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn entry_round_trips_through_its_line() {
+        let entry = AuditEntry {
+            timestamp: 1_715_342_400,
+            command: "use".to_string(),
+            profile: "work@acme.com".to_string(),
+            from: vec![],
+            to: vec!["work@acme.com".to_string(), "work".to_string()],
+            email: r#"Alex "Lex" O\Neil <alex@acme.com>"#.to_string(),
+            keys: vec![pair("id_ed25519", "SHA256:HYB/QOdpdG+GdFhKMOa+6L8")],
+            files: vec![
+                pair(".gitconfig", "9f86d081"),
+                pair("id_ed25519", "60303ae2"),
+            ],
+            user: "alex smith".to_string(),
+            host: String::new(),
+        };
+
+        let line = entry.to_line();
+        assert!(line.contains(r#"email="Alex \"Lex\" O\\Neil <alex@acme.com>""#));
+        assert!(line.contains(r#"files=".gitconfig=9f86d081,id_ed25519=60303ae2""#));
+        assert!(line.contains(r#"host="""#));
+
+        assert_eq!(AuditEntry::parse(&line), Some(entry));
+    }
+
+    #[test]
+    fn parse_skips_invalid_lines() {
+        assert_eq!(AuditEntry::parse(""), None);
+        assert_eq!(AuditEntry::parse("time=1 profile=work"), None);
+        assert_eq!(AuditEntry::parse("time=yesterday command=use"), None);
+
+        let entry = AuditEntry::parse("time=1 command=save future=field").unwrap();
+        assert_eq!(entry.command, "save");
+        assert_eq!(entry.timestamp, 1);
+    }
+}
@@ -1,26 +1,396 @@
+use sha2::{Digest, Sha256};
+use std::env;
 use std::fs;
-use std::io::ErrorKind;
-
+use std::io::{self, ErrorKind, IsTerminal};
+use std::path::{Path, PathBuf};
+
+use crate::agent;
+use crate::aliases;
+use crate::archive;
+use crate::args;
+use crate::audit;
+use crate::completion;
 use crate::constants::{
-    APP_NAME, GITCONFIG_FILE_NAME, PROFILE_NAME_MAX_LENGTH, REMOVING_DIR_ERR, TOGGLE_PREV, VERSION,
+    APP_NAME, CONFIRMATION_REQUIRED_ERR, EXIT_AUDIT_FINDINGS, EXIT_CANCELLED, EXIT_FAILURE,
+    EXIT_LOCKED, EXIT_NOT_FOUND, EXIT_UNSAVED, EXIT_USAGE, GITCONFIG_FILE_NAME, HELP_LINE,
+    LAYERED_GITCONFIG_FILE_NAME, PASSPHRASE_ENV_VAR, PRIVATE_KEY_MODE, PROFILE_MANIFEST_FILE_NAME,
+    PROFILE_NAME_MAX_LENGTH, REMOVING_DIR_ERR, SEALED_FILE_NAME, SSH_AUTH_SOCK_ENV_VAR,
+    SSH_CONFIG_FILE_NAME, TOGGLE_PREV, TRACKED_FILE_NAMES, VERSION,
 };
+use crate::diff;
 use crate::git;
+use crate::history::{self, HistoryRef};
+use crate::json::{self, Json};
+use crate::keys;
+use crate::lock;
+use crate::manifest;
+use crate::picker;
+use crate::settings;
+use crate::ssh_config;
+use crate::store;
+use crate::trash;
 use crate::utils;
 
-pub fn save(profile_name: &str, yes_flag: bool) -> Result<(), String> {
-    let valid_save_examples: String = format!(
-        "Examples:\n    {} save alex\n    {} save alex_2@wi-fi.org",
-        APP_NAME, APP_NAME
+/// A failed command, with the message for stderr and the exit code.
+#[derive(Debug)]
+pub struct CliError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl CliError {
+    pub fn usage(message: String) -> CliError {
+        CliError {
+            code: EXIT_USAGE,
+            message,
+        }
+    }
+
+    pub fn not_found(message: String) -> CliError {
+        CliError {
+            code: EXIT_NOT_FOUND,
+            message,
+        }
+    }
+
+    pub fn cancelled(message: String) -> CliError {
+        CliError {
+            code: EXIT_CANCELLED,
+            message,
+        }
+    }
+
+    pub fn locked(message: String) -> CliError {
+        CliError {
+            code: EXIT_LOCKED,
+            message,
+        }
+    }
+
+    pub fn unsaved(message: String) -> CliError {
+        CliError {
+            code: EXIT_UNSAVED,
+            message,
+        }
+    }
+
+    pub fn audit_findings(message: String) -> CliError {
+        CliError {
+            code: EXIT_AUDIT_FINDINGS,
+            message,
+        }
+    }
+}
+
+/// Errors from helpers that return a plain message are I/O failures.
+impl From<String> for CliError {
+    fn from(message: String) -> CliError {
+        CliError {
+            code: EXIT_FAILURE,
+            message,
+        }
+    }
+}
+
+/// Warns about DSA and short RSA keys, refusing them when the
+/// `refuse_weak_keys` setting is enabled. With JSON output the warnings are
+/// returned instead of printed.
+fn check_weak_keys(
+    app_paths: &utils::AppPaths,
+    entries: &[store::FileEntry],
+    refused_msg: &str,
+    json_flag: bool,
+) -> Result<Vec<String>, String> {
+    let warnings = keys::get_weak_key_warnings(entries);
+
+    if warnings.is_empty() {
+        return Ok(warnings);
+    }
+
+    if settings::get_settings(app_paths).refuse_weak_keys {
+        let lines = [
+            format!(
+                "{}: Weak keys refused by the refuse_weak_keys setting.\n",
+                APP_NAME
+            ),
+            warnings.join("\n"),
+            format!("\n{}", refused_msg),
+        ];
+        return Err(lines.join("\n"));
+    }
+
+    if !json_flag {
+        println!("\nWarning: weak keys found.");
+        for warning in &warnings {
+            println!("  {}", warning);
+        }
+    }
+
+    Ok(warnings)
+}
+
+/// Asks for confirmation. JSON output cannot prompt, so `-y` is required.
+fn confirm(prompt: &str, json_flag: bool) -> Result<bool, CliError> {
+    if json_flag {
+        return Err(CliError::cancelled(format!(
+            "{}: {}",
+            APP_NAME, CONFIRMATION_REQUIRED_ERR
+        )));
+    }
+
+    Ok(utils::confirm(prompt))
+}
+
+/// Returns the saved profiles whose files match the current files.
+fn get_current_profile_names(
+    app_paths: &utils::AppPaths,
+    currfiles_prohash: &utils::ProHash,
+) -> Result<Vec<String>, String> {
+    let gitconfig_data = git::get_gitconfig_data(&app_paths.gitconfig_file_path);
+    let profile_dirs: Vec<String> =
+        utils::get_profile_dirs(&app_paths.data_dir_path).unwrap_or_else(|_| vec![]);
+
+    let mut current_profile_names: Vec<String> = vec![];
+
+    for profile_directory in profile_dirs {
+        let profile_prohash = utils::get_profile_hash(
+            app_paths,
+            gitconfig_data.file_exists,
+            Some(&profile_directory),
+        )?;
+
+        if currfiles_prohash.hash == profile_prohash.hash {
+            current_profile_names.push(profile_directory);
+        }
+    }
+
+    Ok(current_profile_names)
+}
+
+/// The state of the current files for JSON output: the profiles in use, the
+/// tracked files and the Git identity.
+fn get_current_json(app_paths: &utils::AppPaths) -> Result<Json, String> {
+    let gitconfig_data = git::get_gitconfig_data(&app_paths.gitconfig_file_path);
+    let currfiles_prohash = utils::get_profile_hash(app_paths, gitconfig_data.file_exists, None)?;
+    let current_profile_names = if currfiles_prohash.tracked_file_names.is_empty() {
+        vec![]
+    } else {
+        get_current_profile_names(app_paths, &currfiles_prohash)?
+    };
+    let unprotected_key_names = keys::get_unprotected_key_names(
+        &app_paths.ssh_dir_path,
+        &currfiles_prohash.tracked_file_names,
     );
+
+    let gitconfig = gitconfig_data.file_exists.then(|| {
+        Json::object([
+            ("name", Json::from(&gitconfig_data.name)),
+            ("email", Json::from(&gitconfig_data.email)),
+        ])
+    });
+
+    Ok(Json::object([
+        ("profiles", Json::from(current_profile_names)),
+        ("files", Json::from(currfiles_prohash.tracked_file_names)),
+        ("unprotected_keys", Json::from(unprotected_key_names)),
+        ("gitconfig", Json::from(gitconfig)),
+    ]))
+}
+
+/// The changes a command would make, printed by `--dry-run` instead of
+/// making them.
+#[derive(Debug, Default)]
+struct DryRunPlan {
+    deleted: Vec<PathBuf>,
+    written: Vec<PathBuf>,
+    previous_profile: Option<String>,
+    confirmation_required: bool,
+    notes: Vec<String>,
+}
+
+fn print_dry_run(json_command: &str, profile_name: &str, plan: DryRunPlan, json_flag: bool) {
+    let paths = |paths: &[PathBuf]| -> Vec<String> {
+        paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect()
+    };
+
+    if json_flag {
+        json::print_result(
+            json_command,
+            vec![
+                ("dry_run", Json::Bool(true)),
+                (
+                    "profile",
+                    Json::from((!profile_name.is_empty()).then_some(profile_name)),
+                ),
+                ("deleted", Json::from(paths(&plan.deleted))),
+                ("written", Json::from(paths(&plan.written))),
+                ("previous_profile", Json::from(plan.previous_profile)),
+                (
+                    "confirmation_required",
+                    Json::from(plan.confirmation_required),
+                ),
+                ("notes", Json::from(plan.notes)),
+            ],
+        );
+        return;
+    }
+
+    println!("\nDry run, nothing was changed.");
+
+    println!("  would delete ({}):", plan.deleted.len());
+    for path in paths(&plan.deleted) {
+        println!("    {}", path);
+    }
+
+    println!("  would write ({}):", plan.written.len());
+    for path in paths(&plan.written) {
+        println!("    {}", path);
+    }
+
+    if let Some(previous_profile) = &plan.previous_profile {
+        println!("  previous profile would become: {:?}", previous_profile);
+    }
+
+    if plan.confirmation_required {
+        println!("  a confirmation would be asked, unless -y is given");
+    }
+
+    for note in &plan.notes {
+        println!("  {}", note);
+    }
+}
+
+/// Returns the profiles matching the current files, or none when they cannot
+/// be read.
+fn get_profiles_in_use(app_paths: &utils::AppPaths) -> Vec<String> {
+    let gitconfig_data = git::get_gitconfig_data(&app_paths.gitconfig_file_path);
+
+    utils::get_profile_hash(app_paths, gitconfig_data.file_exists, None)
+        .ok()
+        .filter(|prohash| !prohash.tracked_file_names.is_empty())
+        .and_then(|prohash| get_current_profile_names(app_paths, &prohash).ok())
+        .unwrap_or_default()
+}
+
+/// Appends the command, and the identity in use after it, to the audit log.
+/// A log that cannot be written is reported, and the command still succeeds.
+fn record_audit(
+    app_paths: &utils::AppPaths,
+    command: &str,
+    profile_name: &str,
+    from_profile_names: &[String],
+) {
+    let settings = settings::get_settings(app_paths);
+
+    if !settings.audit_log {
+        return;
+    }
+
+    let gitconfig_data = git::get_gitconfig_data(&app_paths.gitconfig_file_path);
+    let current_entries = utils::get_profile_hash(app_paths, gitconfig_data.file_exists, None)
+        .ok()
+        .and_then(|prohash| store::read_current_entries(app_paths, &prohash).ok())
+        .unwrap_or_default();
+
+    let entry = audit::AuditEntry {
+        timestamp: history::get_now(),
+        command: command.to_string(),
+        profile: profile_name.to_string(),
+        from: from_profile_names.to_vec(),
+        to: get_profiles_in_use(app_paths),
+        email: gitconfig_data.email,
+        keys: current_entries
+            .iter()
+            .filter(|entry| utils::is_private_key_file_name(&entry.name))
+            .map(|entry| {
+                (
+                    entry.name.clone(),
+                    get_key_file_fingerprint(&current_entries, &entry.name),
+                )
+            })
+            .collect(),
+        files: current_entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.name.clone(),
+                    format!("{:x}", Sha256::digest(&entry.content)),
+                )
+            })
+            .collect(),
+        user: audit::get_user(),
+        host: audit::get_host(),
+    };
+
+    if let Err(e) = audit::append(app_paths, &settings, &entry) {
+        eprintln!(
+            "{}: Warning: Could not write the audit log: {}\n{}",
+            APP_NAME,
+            app_paths.audit_log_file_path.display(),
+            e
+        );
+    }
+}
+
+/// Takes the lock on `~/.xks` for a command that changes files.
+pub fn lock(wait_flag: bool) -> Result<lock::AppLock, CliError> {
+    lock::acquire(&utils::get_app_paths(), wait_flag).map_err(|error| match error {
+        lock::LockError::Busy(message) => CliError::locked(message),
+        lock::LockError::Failed(message) => CliError::from(message),
+    })
+}
+
+/// Resolves an alias, or a unique prefix, to the saved profile name.
+fn resolve_profile_name(profile_name: &str) -> Result<String, CliError> {
+    let app_paths = utils::get_app_paths();
+    let profile_dirs: Vec<String> =
+        utils::get_profile_dirs(&app_paths.data_dir_path).unwrap_or_else(|_| vec![]);
+    let aliases = aliases::read_aliases(&app_paths);
+
+    aliases::resolve_profile_name(&aliases, &profile_dirs, profile_name, true).map_err(|matches| {
+        CliError::usage(format!(
+            "{}: Profile name {:?} is ambiguous, it matches: {}",
+            APP_NAME,
+            profile_name,
+            matches.join(", ")
+        ))
+    })
+}
+
+/// Resolves an alias to the saved profile name, for the commands that change
+/// or remove a profile, where an expanded prefix could pick the wrong one.
+fn resolve_full_profile_name(profile_name: &str) -> Result<String, CliError> {
+    let app_paths = utils::get_app_paths();
+    let profile_dirs: Vec<String> =
+        utils::get_profile_dirs(&app_paths.data_dir_path).unwrap_or_else(|_| vec![]);
+    let aliases = aliases::read_aliases(&app_paths);
+
+    aliases::resolve_profile_name(&aliases, &profile_dirs, profile_name, false).map_err(
+        |matches| {
+            CliError::not_found(format!(
+                "{}: Profile {:?} not found, this command needs the full profile name or an alias.\n\nDid you mean: {}",
+                APP_NAME,
+                profile_name,
+                matches.join(", ")
+            ))
+        },
+    )
+}
+
+/// Checks the name of a new profile, `valid_examples` is shown on errors.
+fn validate_profile_name(profile_name: &str, valid_examples: String) -> Result<(), CliError> {
     let valid_chars = |c: char| c.is_ascii_alphanumeric() || "@-_.".contains(c);
 
     if profile_name.is_empty() {
         let lines = [
             format!("{}: Profile name cannot be empty.\n", APP_NAME),
-            valid_save_examples,
+            valid_examples,
         ];
         let msg = lines.join("\n");
-        return Err(msg);
+        return Err(CliError::usage(msg));
     }
 
     if !profile_name.starts_with(|c: char| c.is_ascii_alphanumeric())
@@ -29,20 +399,20 @@ pub fn save(profile_name: &str, yes_flag: bool) -> Result<(), String> {
         let lines = [
             format!("{}: Invalid profile name {:?}\n", APP_NAME, profile_name),
             "Profile names must start and end with a letter or number.\n".to_string(),
-            valid_save_examples,
+            valid_examples,
         ];
         let msg = lines.join("\n");
-        return Err(msg);
+        return Err(CliError::usage(msg));
     }
 
     if !profile_name.chars().all(valid_chars) {
         let lines = [
             format!("{}: Invalid profile name {:?}\n", APP_NAME, profile_name),
             "Profile names can only contain: (letters, numbers, @, -, _, .)\n".to_string(),
-            valid_save_examples,
+            valid_examples,
         ];
         let msg = lines.join("\n");
-        return Err(msg);
+        return Err(CliError::usage(msg));
     }
 
     if profile_name.chars().count() > PROFILE_NAME_MAX_LENGTH {
@@ -51,23 +421,46 @@ pub fn save(profile_name: &str, yes_flag: bool) -> Result<(), String> {
                 "{}: Profile name cannot exceed {} characters.\n",
                 APP_NAME, PROFILE_NAME_MAX_LENGTH
             ),
-            valid_save_examples,
+            valid_examples,
         ];
         let msg = lines.join("\n");
-        return Err(msg);
+        return Err(CliError::usage(msg));
     }
 
+    Ok(())
+}
+
+pub fn save(
+    profile_name: &str,
+    yes_flag: bool,
+    json_flag: bool,
+    dry_run: bool,
+) -> Result<(), CliError> {
+    // new names are allowed, so only an exact alias is resolved
+    let app_paths = utils::get_app_paths();
+    let alias_target = match app_paths.data_dir_path.join(profile_name).is_dir() {
+        true => None,
+        false => aliases::find_alias(&app_paths, profile_name),
+    };
+    let profile_name = alias_target.as_deref().unwrap_or(profile_name);
+    let valid_save_examples: String = format!(
+        "Examples:\n    {} save alex\n    {} save alex_2@wi-fi.org",
+        APP_NAME, APP_NAME
+    );
+
+    validate_profile_name(profile_name, valid_save_examples)?;
+
     let app_paths = utils::get_app_paths();
     let gitconfig_data = git::get_gitconfig_data(&app_paths.gitconfig_file_path);
     let profile_path = app_paths.data_dir_path.join(profile_name);
 
     let currfiles_prohash = utils::get_profile_hash(&app_paths, gitconfig_data.file_exists, None)?;
 
-    if currfiles_prohash.tracked_file_names.len() == 0 {
-        return Err(format!(
+    if currfiles_prohash.tracked_file_names.is_empty() {
+        return Err(CliError::not_found(format!(
             "{}: Current files not found.\n\nNo profile was saved.",
             APP_NAME
-        ));
+        )));
     }
 
     let profile_dirs: Vec<String> =
@@ -87,66 +480,353 @@ pub fn save(profile_name: &str, yes_flag: bool) -> Result<(), String> {
         }
     }
 
-    let save_profile = || -> Result<(), String> {
-        if let Err(err) = fs::remove_dir_all(&profile_path) {
-            if err.kind() != ErrorKind::NotFound {
+    let current_entries = store::read_current_entries(&app_paths, &currfiles_prohash)?;
+    let previous_profile_names = get_profiles_in_use(&app_paths);
+
+    let warnings = check_weak_keys(
+        &app_paths,
+        &current_entries,
+        "No profile was saved.",
+        json_flag,
+    )?;
+
+    let profile_is_sealed = store::is_sealed(&profile_path);
+    let profile_manifest = manifest::read_manifest(&profile_path);
+
+    // layered profiles keep only the gitconfig keys that differ from the base,
+    // and the whole file as it is, which `use` writes back
+    let mut saved_entries = current_entries.clone();
+    let mut full_gitconfig: Option<Vec<u8>> = None;
+
+    if let Some(base_content) = store::read_base_gitconfig(&profile_path)?
+        && let Some(entry) = saved_entries
+            .iter_mut()
+            .find(|entry| entry.name == GITCONFIG_FILE_NAME)
+    {
+        let overlay = git::get_gitconfig_overlay(
+            &git::parse_gitconfig(&base_content),
+            &git::parse_gitconfig(&String::from_utf8_lossy(&entry.content)),
+        );
+
+        full_gitconfig = Some(std::mem::replace(
+            &mut entry.content,
+            git::render_gitconfig(&overlay).into_bytes(),
+        ));
+    }
+
+    let print_saved = || -> Result<(), CliError> {
+        record_audit(&app_paths, "save", profile_name, &previous_profile_names);
+
+        if json_flag {
+            json::print_result(
+                "save",
+                vec![
+                    ("profile", Json::from(profile_name)),
+                    ("warnings", Json::from(warnings.clone())),
+                    ("current", get_current_json(&app_paths)?),
+                ],
+            );
+        } else {
+            println!("\nProfile {:?} saved successfully!", profile_name);
+        }
+        Ok(())
+    };
+
+    let save_profile = || -> Result<(), CliError> {
+        if profile_is_sealed {
+            let prompt = format!("Passphrase for profile {:?}: ", profile_name);
+            let passphrase = utils::read_passphrase(&prompt)?;
+
+            // fails on a wrong passphrase, so the profile keeps a single one
+            store::open_sealed(&profile_path, &passphrase)?;
+
+            if let Err(err) = fs::remove_dir_all(&profile_path) {
                 eprintln!("{}: {}\n", APP_NAME, REMOVING_DIR_ERR);
-                return Err(err.to_string());
+                return Err(err.to_string().into());
             }
+
+            let seal_key = store::get_or_create_seal_key(&app_paths)?;
+            store::write_sealed(&profile_path, &current_entries, &passphrase, &seal_key)?;
+            manifest::write_manifest(&profile_path, &profile_manifest).ok();
+
+            return print_saved();
         }
 
-        if gitconfig_data.file_exists {
-            if let Err(_) = utils::copy_file(
-                &app_paths.gitconfig_file_path,
-                &profile_path.join(GITCONFIG_FILE_NAME),
-            ) {
-                return Err(format!(
-                    "{}: Error: Could not copy file: {}",
-                    APP_NAME, GITCONFIG_FILE_NAME
-                ));
+        if let Err(err) = fs::remove_dir_all(&profile_path)
+            && err.kind() != ErrorKind::NotFound
+        {
+            eprintln!("{}: {}\n", APP_NAME, REMOVING_DIR_ERR);
+            return Err(err.to_string().into());
+        }
+
+        for entry in &saved_entries {
+            if utils::write_tracked_file(profile_path.join(&entry.name), &entry.content).is_err() {
+                return Err(
+                    format!("{}: Error: Could not copy file: {}", APP_NAME, entry.name).into(),
+                );
             }
         }
 
-        for filename in currfiles_prohash.tracked_file_names {
-            if filename != GITCONFIG_FILE_NAME {
-                if let Err(_) = utils::copy_file(
-                    &app_paths.ssh_dir_path.join(&filename),
-                    &profile_path.join(&filename),
-                ) {
-                    return Err(format!(
-                        "{}: Error: Could not copy file: {}",
-                        APP_NAME, filename
-                    ));
-                }
+        if manifest::write_manifest(&profile_path, &profile_manifest).is_err() {
+            return Err(format!(
+                "{}: Error: Could not write file: {}",
+                APP_NAME, PROFILE_MANIFEST_FILE_NAME
+            )
+            .into());
+        }
+
+        if let Some(full_gitconfig) = &full_gitconfig
+            && utils::write_tracked_file(
+                profile_path.join(LAYERED_GITCONFIG_FILE_NAME),
+                full_gitconfig,
+            )
+            .is_err()
+        {
+            return Err(format!(
+                "{}: Error: Could not write file: {}",
+                APP_NAME, LAYERED_GITCONFIG_FILE_NAME
+            )
+            .into());
+        }
+
+        print_saved()
+    };
+
+    if dry_run {
+        let mut plan = DryRunPlan {
+            confirmation_required: profile_already_exists_and_has_changes && !yes_flag,
+            ..DryRunPlan::default()
+        };
+        let written_names: Vec<&str> = if profile_is_sealed {
+            vec![SEALED_FILE_NAME]
+        } else {
+            saved_entries
+                .iter()
+                .map(|entry| entry.name.as_str())
+                .collect()
+        };
+
+        // the profile directory is replaced, files not written again are lost
+        for filename in utils::get_files(&profile_path).unwrap_or_default() {
+            if !written_names.contains(&filename.as_str())
+                && filename != PROFILE_MANIFEST_FILE_NAME
+                && !(filename == LAYERED_GITCONFIG_FILE_NAME && full_gitconfig.is_some())
+            {
+                plan.deleted.push(profile_path.join(filename));
             }
         }
 
-        println!("\nProfile {:?} saved successfully!", profile_name);
+        plan.written = written_names
+            .iter()
+            .map(|filename| profile_path.join(filename))
+            .collect();
+
+        if full_gitconfig.is_some() {
+            plan.written
+                .push(profile_path.join(LAYERED_GITCONFIG_FILE_NAME));
+        }
+
+        if profile_is_sealed {
+            plan.notes
+                .push("the profile is sealed, the passphrase would be asked".to_string());
+        }
+
+        print_dry_run("save", profile_name, plan, json_flag);
         return Ok(());
-    };
+    }
 
     if profile_already_exists_and_has_changes && !yes_flag {
         let prompt = "The current files have been modified.\nDo you want to save the changes?";
 
-        if utils::confirm(prompt) {
+        if confirm(prompt, json_flag)? {
             return save_profile();
         } else {
-            println!("\nNo profile was saved.");
-            return Ok(());
+            return Err(CliError::cancelled("\nNo profile was saved.".to_string()));
         }
     }
 
     save_profile()
 }
 
-pub fn remove(profile_name: &str, yes_flag: bool) -> Result<(), String> {
+/// Moves or copies a profile to a new name. Renaming also updates the
+/// previous-profile pointer and the base of layered profiles.
+fn move_profile(
+    source_name: &str,
+    target_name: &str,
+    yes_flag: bool,
+    keep_source: bool,
+) -> Result<(), CliError> {
+    let resolved_name = if keep_source {
+        resolve_profile_name(source_name)?
+    } else {
+        resolve_full_profile_name(source_name)?
+    };
+    let source_name = resolved_name.as_str();
+    let (command, action, past) = if keep_source {
+        ("copy", "copy", "copied")
+    } else {
+        ("rename", "rename", "renamed")
+    };
+
+    if source_name.is_empty() {
+        let lines = [
+            format!("{}: Profile name cannot be empty.\n", APP_NAME),
+            format!("Example:\n    {} {} alex alex_2", APP_NAME, command),
+        ];
+        let msg = lines.join("\n");
+        return Err(CliError::usage(msg));
+    }
+
+    validate_profile_name(
+        target_name,
+        format!(
+            "Examples:\n    {} {} alex alex_2\n    {} {} alex alex_2@wi-fi.org",
+            APP_NAME, command, APP_NAME, command
+        ),
+    )?;
+
+    let app_paths = utils::get_app_paths();
+    let source_path = app_paths.data_dir_path.join(source_name);
+    let target_path = app_paths.data_dir_path.join(target_name);
+
+    let not_done_msg = format!("No profile was {}.", past);
+
+    if !source_path.is_dir() {
+        return Err(CliError::not_found(format!(
+            "{}: Profile {:?} not found.\n\n{}",
+            APP_NAME, source_name, not_done_msg
+        )));
+    }
+
+    if source_name == target_name {
+        return Err(CliError::usage(format!(
+            "{}: Source and target profiles are the same.\n\n{}",
+            APP_NAME, not_done_msg
+        )));
+    }
+
+    let target_layered_profiles =
+        manifest::get_layered_profiles(&app_paths.data_dir_path, target_name);
+
+    if !target_layered_profiles.is_empty() {
+        return Err(format!(
+            "{}: Profile {:?} is the base of: {}\n\n{}",
+            APP_NAME,
+            target_name,
+            target_layered_profiles.join(", "),
+            not_done_msg
+        )
+        .into());
+    }
+
+    let move_files = || -> Result<(), CliError> {
+        // the profile written over is kept in the trash, like a removed one
+        if target_path.exists() {
+            let trash_entry = trash::move_to_trash(&app_paths, target_name).map_err(|e| {
+                format!(
+                    "{}: Error: Could not move the profile to the trash: {}\n\n{}",
+                    APP_NAME, target_name, e
+                )
+            })?;
+
+            println!(
+                "\nThe previous profile {:?} was moved to the trash as {:?}.",
+                target_name,
+                trash_entry.get_trash_name()
+            );
+        }
+
+        if keep_source {
+            for filename in utils::get_files(&source_path)? {
+                let content = fs::read(source_path.join(&filename)).map_err(|_| {
+                    format!("{}: Error: Could not read file: {}", APP_NAME, filename)
+                })?;
+
+                let target_file_path = target_path.join(&filename);
+                let copied =
+                    utils::write_tracked_file(&target_file_path, &content).and_then(|_| {
+                        match utils::get_mode(source_path.join(&filename)) {
+                            Some(mode) => utils::set_mode(&target_file_path, mode),
+                            None => Ok(()),
+                        }
+                    });
+
+                if copied.is_err() {
+                    return Err(
+                        format!("{}: Error: Could not copy file: {}", APP_NAME, filename).into(),
+                    );
+                }
+            }
+        } else {
+            if let Err(err) = fs::rename(&source_path, &target_path) {
+                eprintln!("{}: Error: Could not rename directory.\n", APP_NAME);
+                return Err(err.to_string().into());
+            }
+
+            if utils::read_first_line(&app_paths.previous_profile_file_path) == source_name {
+                utils::write_to_file(app_paths.previous_profile_file_path.clone(), target_name)
+                    .ok();
+            }
+
+            aliases::retarget_aliases(&app_paths, source_name, Some(target_name));
+            history::rename_profile(&app_paths, source_name, target_name);
+
+            for layered_profile in
+                manifest::get_layered_profiles(&app_paths.data_dir_path, source_name)
+            {
+                let layered_path = app_paths.data_dir_path.join(&layered_profile);
+                let mut layered_manifest = manifest::read_manifest(&layered_path);
+                layered_manifest.base = Some(target_name.to_string());
+                manifest::write_manifest(&layered_path, &layered_manifest).ok();
+            }
+        }
+
+        println!(
+            "\nProfile {:?} {} to {:?} successfully!",
+            source_name, past, target_name
+        );
+        Ok(())
+    };
+
+    if !target_path.exists() || yes_flag {
+        return move_files();
+    }
+
+    let prompt = format!(
+        "Profile {:?} already exists.\nThis action will {} {:?} over it, moving the existing one to the trash.\nAre you sure you want to proceed?",
+        target_name, action, source_name
+    );
+
+    if utils::confirm(prompt.as_str()) {
+        move_files()
+    } else {
+        Err(CliError::cancelled(format!("\n{}", not_done_msg)))
+    }
+}
+
+pub fn rename(source_name: &str, target_name: &str, yes_flag: bool) -> Result<(), CliError> {
+    move_profile(source_name, target_name, yes_flag, false)
+}
+
+pub fn copy(source_name: &str, target_name: &str, yes_flag: bool) -> Result<(), CliError> {
+    move_profile(source_name, target_name, yes_flag, true)
+}
+
+pub fn remove(
+    profile_name: &str,
+    yes_flag: bool,
+    json_flag: bool,
+    dry_run: bool,
+) -> Result<(), CliError> {
+    let resolved_name = resolve_full_profile_name(profile_name)?;
+    let profile_name = resolved_name.as_str();
     if profile_name.is_empty() {
         let lines = [
             format!("{}: Profile name cannot be empty.\n", APP_NAME),
             format!("Example:\n    {} remove alex", APP_NAME),
         ];
         let msg = lines.join("\n");
-        return Err(msg);
+        return Err(CliError::usage(msg));
     }
 
     let app_paths = utils::get_app_paths();
@@ -159,69 +839,315 @@ pub fn remove(profile_name: &str, yes_flag: bool) -> Result<(), String> {
     );
 
     if !profile_exists {
-        return Err(non_existing_profile_msg);
+        return Err(CliError::not_found(non_existing_profile_msg));
     }
 
-    let remove_profile = || -> Result<(), String> {
-        if let Err(err) = fs::remove_dir_all(&profile_path) {
-            if err.kind() == ErrorKind::NotFound {
-                return Err(non_existing_profile_msg);
-            } else {
-                eprintln!("{}: {}\n", APP_NAME, REMOVING_DIR_ERR);
-                return Err(err.to_string());
+    let layered_profiles = manifest::get_layered_profiles(&app_paths.data_dir_path, profile_name);
+
+    if !layered_profiles.is_empty() {
+        return Err(format!(
+            "{}: Profile {:?} is the base of: {}\n\nNo profile was removed.",
+            APP_NAME,
+            profile_name,
+            layered_profiles.join(", ")
+        )
+        .into());
+    }
+
+    let previous_profile_names = get_profiles_in_use(&app_paths);
+
+    let remove_profile = || -> Result<(), CliError> {
+        let trash_entry = match trash::move_to_trash(&app_paths, profile_name) {
+            Ok(trash_entry) => trash_entry,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                return Err(CliError::not_found(non_existing_profile_msg));
+            }
+            Err(err) => {
+                eprintln!(
+                    "{}: Error: Could not move the profile to the trash: {}\n",
+                    APP_NAME,
+                    app_paths.trash_dir_path.display()
+                );
+                return Err(err.to_string().into());
             }
+        };
+
+        aliases::retarget_aliases(&app_paths, profile_name, None);
+        record_audit(&app_paths, "remove", profile_name, &previous_profile_names);
+
+        if json_flag {
+            json::print_result(
+                "remove",
+                vec![
+                    ("profile", Json::from(profile_name)),
+                    ("trash_name", Json::from(trash_entry.get_trash_name())),
+                ],
+            );
+        } else {
+            println!(
+                "\nProfile {:?} moved to the trash.\nRestore it with:\n    {} trash restore {}",
+                profile_name, APP_NAME, profile_name
+            );
         }
+        Ok(())
+    };
 
-        println!("\nProfile {:?} removed successfully!", profile_name);
+    if dry_run {
+        let plan = DryRunPlan {
+            deleted: utils::get_files(&profile_path)?
+                .into_iter()
+                .map(|filename| profile_path.join(filename))
+                .collect(),
+            confirmation_required: !yes_flag,
+            notes: vec![format!(
+                "the profile would be moved to {}",
+                app_paths.trash_dir_path.display()
+            )],
+            ..DryRunPlan::default()
+        };
+
+        print_dry_run("remove", profile_name, plan, json_flag);
         return Ok(());
-    };
+    }
 
     if yes_flag {
         return remove_profile();
     }
 
     let prompt = format!(
-        "This action will delete the profile {:?} and all its files.\nAre you sure you want to proceed?",
+        "This action will move the profile {:?} and all its files to the trash.\nAre you sure you want to proceed?",
         profile_name
     );
 
-    if utils::confirm(prompt.as_str()) {
-        return remove_profile();
+    if confirm(prompt.as_str(), json_flag)? {
+        remove_profile()
     } else {
-        println!("\nNo profile was removed.");
-        return Ok(());
+        Err(CliError::cancelled("\nNo profile was removed.".to_string()))
     }
 }
 
-pub fn use_profile(input_profile_name: &str, yes_flag: bool) -> Result<(), String> {
-    if input_profile_name.is_empty() {
-        let lines = [
-            format!("{}: Profile name cannot be empty.\n", APP_NAME),
-            format!("Example:\n    {} use alex", APP_NAME),
-        ];
-        let msg = lines.join("\n");
-        return Err(msg);
-    }
+/// What the picker and the dashboard show of a profile. Sealed profiles are
+/// not opened, so they have no identity or keys.
+pub struct ProfileSummary {
+    pub name: String,
+    pub active: bool,
+    pub sealed: bool,
+    pub git_name: String,
+    pub email: String,
+    /// The name and fingerprint of every private key.
+    pub keys: Vec<(String, String)>,
+}
 
+pub fn get_profile_summaries() -> Result<Vec<ProfileSummary>, String> {
     let app_paths = utils::get_app_paths();
     let gitconfig_data = git::get_gitconfig_data(&app_paths.gitconfig_file_path);
-
     let profile_dirs: Vec<String> =
         utils::get_profile_dirs(&app_paths.data_dir_path).unwrap_or_else(|_| vec![]);
 
     let currfiles_prohash = utils::get_profile_hash(&app_paths, gitconfig_data.file_exists, None)?;
+    let current_profile_names = get_current_profile_names(&app_paths, &currfiles_prohash)?;
 
-    let mut current_profile_names: Vec<String> = vec![];
-    let mut is_profile_saved: bool = false;
+    let mut summaries: Vec<ProfileSummary> = vec![];
 
-    for profile_directory in &profile_dirs {
-        let profile_prohash = utils::get_profile_hash(
-            &app_paths,
-            gitconfig_data.file_exists,
-            Some(profile_directory),
-        )?;
+    for profile_directory in profile_dirs {
+        let profile_path = app_paths.data_dir_path.join(&profile_directory);
+        let mut summary = ProfileSummary {
+            active: current_profile_names.contains(&profile_directory),
+            sealed: store::is_sealed(&profile_path),
+            name: profile_directory,
+            git_name: String::new(),
+            email: String::new(),
+            keys: vec![],
+        };
+
+        if !summary.sealed {
+            let entries = store::read_profile_entries(&app_paths, &profile_path, &summary.name)?;
+
+            if let Some(gitconfig_entry) = entries
+                .iter()
+                .find(|entry| entry.name == GITCONFIG_FILE_NAME)
+            {
+                let profile_gitconfig_data = git::get_gitconfig_content_data(
+                    &String::from_utf8_lossy(&gitconfig_entry.content),
+                );
+                summary.git_name = profile_gitconfig_data.name;
+                summary.email = profile_gitconfig_data.email;
+            }
 
-        if currfiles_prohash.hash == profile_prohash.hash {
+            summary.keys = entries
+                .iter()
+                .filter(|entry| utils::is_private_key_file_name(&entry.name))
+                .map(|entry| {
+                    (
+                        entry.name.clone(),
+                        get_key_file_fingerprint(&entries, &entry.name),
+                    )
+                })
+                .collect();
+        }
+
+        summaries.push(summary);
+    }
+
+    Ok(summaries)
+}
+
+/// Resolves the `-N` and `@{<time>}` history lookups of `use`, and otherwise
+/// an alias or a unique prefix.
+fn resolve_use_profile_name(profile_name: &str) -> Result<String, CliError> {
+    let Some(history_ref) = history::parse_history_ref(profile_name, history::get_now()) else {
+        return resolve_profile_name(profile_name);
+    };
+
+    let history_ref = history_ref.map_err(|e| {
+        CliError::usage(format!(
+            "{}: {}\n\nExamples:\n    {} use -2\n    {} use @{{yesterday}}\n    {} use @{{3.days.ago}}",
+            APP_NAME, e, APP_NAME, APP_NAME, APP_NAME
+        ))
+    })?;
+
+    let app_paths = utils::get_app_paths();
+    let entries = history::read_history(&app_paths);
+    let history_hint = format!("See:\n    {} history", APP_NAME);
+
+    match history_ref {
+        HistoryRef::Steps(steps) => {
+            let gitconfig_data = git::get_gitconfig_data(&app_paths.gitconfig_file_path);
+            let currfiles_prohash =
+                utils::get_profile_hash(&app_paths, gitconfig_data.file_exists, None)?;
+            let current_profile_names = get_current_profile_names(&app_paths, &currfiles_prohash)?;
+
+            // profiles in use or removed since are skipped
+            let recent_profiles: Vec<String> = history::get_recent_profiles(&entries)
+                .into_iter()
+                .filter(|name| {
+                    !current_profile_names.contains(name)
+                        && app_paths.data_dir_path.join(name).is_dir()
+                })
+                .collect();
+
+            recent_profiles.get(steps - 1).cloned().ok_or_else(|| {
+                CliError::not_found(format!(
+                    "{}: Cannot go back {} profiles, the history holds {} other saved profiles.\n\n{}",
+                    APP_NAME,
+                    steps,
+                    recent_profiles.len(),
+                    history_hint
+                ))
+            })
+        }
+        HistoryRef::At(timestamp) => {
+            let Some(profile_name_then) = history::get_profile_at(&entries, timestamp) else {
+                return Err(CliError::not_found(format!(
+                    "{}: No profile switch recorded before {}.\n\n{}",
+                    APP_NAME,
+                    history::format_timestamp(timestamp),
+                    history_hint
+                )));
+            };
+
+            if !app_paths.data_dir_path.join(&profile_name_then).is_dir() {
+                return Err(CliError::not_found(format!(
+                    "{}: Profile {:?}, in use at {}, no longer exists.",
+                    APP_NAME,
+                    profile_name_then,
+                    history::format_timestamp(timestamp)
+                )));
+            }
+
+            Ok(profile_name_then)
+        }
+    }
+}
+
+/// Lets the user pick a saved profile, showing its Git email and the
+/// fingerprint of its first key.
+fn pick_profile() -> Result<String, CliError> {
+    let summaries = get_profile_summaries()?;
+
+    if summaries.is_empty() {
+        return Err(format!("{}: No saved profiles available to use.", APP_NAME).into());
+    }
+
+    let items: Vec<picker::PickerItem> = summaries
+        .into_iter()
+        .map(|summary| {
+            let detail = if summary.sealed {
+                "(sealed)".to_string()
+            } else {
+                let email_label = if summary.email.is_empty() {
+                    "(no email)"
+                } else {
+                    summary.email.as_str()
+                };
+                let fingerprint = summary
+                    .keys
+                    .first()
+                    .map(|(_, fingerprint)| fingerprint.as_str())
+                    .unwrap_or("(no keys)");
+
+                format!("{}  {}", email_label, fingerprint)
+            };
+
+            picker::PickerItem {
+                name: summary.name,
+                detail,
+                keywords: summary.email,
+                active: summary.active,
+            }
+        })
+        .collect();
+
+    let title =
+        "Select a profile to use (type to filter, arrows to move, Enter to use, Esc to cancel)";
+
+    match picker::pick(title, &items)? {
+        Some(profile_name) => Ok(profile_name),
+        None => Err(CliError::cancelled(
+            "\nProfile switch canceled.".to_string(),
+        )),
+    }
+}
+
+pub fn use_profile(
+    input_profile_name: &str,
+    yes_flag: bool,
+    json_flag: bool,
+    dry_run: bool,
+) -> Result<(), CliError> {
+    let resolved_name = if !input_profile_name.is_empty() {
+        resolve_use_profile_name(input_profile_name)?
+    } else if !json_flag && picker::is_interactive() {
+        pick_profile()?
+    } else {
+        let lines = [
+            format!("{}: Profile name cannot be empty.\n", APP_NAME),
+            format!("Example:\n    {} use alex", APP_NAME),
+        ];
+        let msg = lines.join("\n");
+        return Err(CliError::usage(msg));
+    };
+    let input_profile_name = resolved_name.as_str();
+
+    let app_paths = utils::get_app_paths();
+    let gitconfig_data = git::get_gitconfig_data(&app_paths.gitconfig_file_path);
+
+    let profile_dirs: Vec<String> =
+        utils::get_profile_dirs(&app_paths.data_dir_path).unwrap_or_else(|_| vec![]);
+
+    let currfiles_prohash = utils::get_profile_hash(&app_paths, gitconfig_data.file_exists, None)?;
+
+    let mut current_profile_names: Vec<String> = vec![];
+    let mut is_profile_saved: bool = false;
+
+    for profile_directory in &profile_dirs {
+        let profile_prohash = utils::get_profile_hash(
+            &app_paths,
+            gitconfig_data.file_exists,
+            Some(profile_directory),
+        )?;
+
+        if currfiles_prohash.hash == profile_prohash.hash {
             is_profile_saved = true;
             current_profile_names.push(profile_directory.clone());
         }
@@ -246,73 +1172,206 @@ pub fn use_profile(input_profile_name: &str, yes_flag: bool) -> Result<(), Strin
         return Err(format!(
             "{}: No saved profiles available to use.\n\n{}",
             APP_NAME, profile_in_use_msg
-        ));
+        )
+        .into());
     } else if new_profile_name == TOGGLE_PREV {
         return Err(format!(
             "{}: No saved profiles different from the current one.\n\n{}",
             APP_NAME, profile_in_use_msg
-        ));
+        )
+        .into());
     } else if !profile_exists {
-        return Err(format!(
+        return Err(CliError::not_found(format!(
             "{}: Profile {:?} not found.\n\n{}",
             APP_NAME, new_profile_name, profile_in_use_msg
-        ));
+        )));
     }
 
-    let change_profile = || -> Result<(), String> {
+    let new_profile_entries =
+        store::read_profile_entries(&app_paths, &new_profile_source_path, &new_profile_name)?;
+
+    let warnings = check_weak_keys(
+        &app_paths,
+        &new_profile_entries,
+        &format!("Profile switch canceled.\n\n{}", profile_in_use_msg),
+        json_flag,
+    )?;
+
+    let settings = settings::get_settings(&app_paths);
+
+    let outgoing_entries = if settings.ssh_agent {
+        store::read_current_entries(&app_paths, &currfiles_prohash).unwrap_or_default()
+    } else {
+        vec![]
+    };
+
+    let current_assembled_config = ssh_config::read_current_assembled(&app_paths);
+    let assemble_ssh_config = current_assembled_config.is_some()
+        || !ssh_config::read_base_config(&app_paths).trim().is_empty();
+
+    let change_profile = || -> Result<(), CliError> {
         for filename in &currfiles_prohash.tracked_file_names {
-            let file_to_remove_path = if filename == GITCONFIG_FILE_NAME {
-                app_paths.gitconfig_file_path.clone()
-            } else {
-                app_paths.ssh_dir_path.join(filename)
-            };
+            let file_to_remove_path = utils::get_current_file_path(&app_paths, filename);
 
             if let Err(err) = fs::remove_file(file_to_remove_path) {
                 eprintln!("{}: Error: Could not remove file: {}\n", APP_NAME, filename);
-                return Err(err.to_string());
+                return Err(err.to_string().into());
             }
         }
 
-        let new_profile_file_names: Vec<String> =
-            utils::get_files(&new_profile_source_path).unwrap_or_else(|_| vec![]);
+        for entry in &new_profile_entries {
+            if assemble_ssh_config && entry.name == SSH_CONFIG_FILE_NAME {
+                continue;
+            }
+
+            let destination_file_path = utils::get_current_file_path(&app_paths, &entry.name);
 
-        for filename in new_profile_file_names {
-            let destination_file_path = if filename == GITCONFIG_FILE_NAME {
-                &app_paths.gitconfig_file_path
-            } else {
-                &app_paths.ssh_dir_path.join(&filename)
-            };
+            if utils::write_tracked_file(&destination_file_path, &entry.content).is_err() {
+                return Err(
+                    format!("{}: Error: Could not copy file: {}", APP_NAME, entry.name).into(),
+                );
+            }
+        }
 
-            if let Err(_) = utils::copy_file(
-                &new_profile_source_path.join(&filename),
-                destination_file_path,
-            ) {
+        if assemble_ssh_config {
+            let fragment = new_profile_entries
+                .iter()
+                .find(|entry| entry.name == SSH_CONFIG_FILE_NAME)
+                .map(|entry| String::from_utf8_lossy(&entry.content).into_owned())
+                .unwrap_or_default();
+            let unmanaged = current_assembled_config
+                .as_ref()
+                .map(|assembled| assembled.unmanaged.as_str())
+                .unwrap_or_default();
+
+            if ssh_config::write_current_config(&app_paths, &fragment, unmanaged).is_err() {
                 return Err(format!(
                     "{}: Error: Could not copy file: {}",
-                    APP_NAME, filename
-                ));
+                    APP_NAME, SSH_CONFIG_FILE_NAME
+                )
+                .into());
             }
         }
 
-        if current_profile_names.len() > 0 {
+        if !current_profile_names.is_empty() {
             utils::write_to_file(
-                app_paths.previous_profile_file_path,
+                app_paths.previous_profile_file_path.clone(),
                 &current_profile_names[0],
             )
             .ok();
         }
 
+        history::record_switch(
+            &app_paths,
+            current_profile_names.first().map(String::as_str),
+            &new_profile_name,
+        )
+        .ok();
+
+        record_audit(&app_paths, "use", &new_profile_name, &current_profile_names);
+
+        let agent_changes = settings.ssh_agent.then(|| {
+            agent::switch_identities(
+                &outgoing_entries,
+                &new_profile_entries,
+                settings.ssh_agent_lifetime,
+            )
+        });
+
+        if json_flag {
+            let ssh_agent = agent_changes.map(|changes| {
+                Json::object([
+                    ("reachable", Json::from(changes.is_some())),
+                    ("changes", Json::from(changes.unwrap_or_default())),
+                ])
+            });
+
+            json::print_result(
+                "use",
+                vec![
+                    ("profile", Json::from(&new_profile_name)),
+                    (
+                        "previous_profiles",
+                        Json::from(current_profile_names.clone()),
+                    ),
+                    ("warnings", Json::from(warnings.clone())),
+                    ("ssh_agent", Json::from(ssh_agent)),
+                    ("current", get_current_json(&app_paths)?),
+                ],
+            );
+            return Ok(());
+        }
+
         println!(
             "\nProfile switched successfully!\n\nUsing profile: {:?}",
             new_profile_name
         );
-        return Ok(());
+
+        match agent_changes {
+            Some(None) => println!(
+                "\nWarning: ssh-agent not reachable, check ${}.",
+                SSH_AUTH_SOCK_ENV_VAR
+            ),
+            Some(Some(changes)) if !changes.is_empty() => {
+                println!("\nssh-agent:");
+                for change in changes {
+                    println!("  {}", change);
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
     };
 
-    if is_profile_saved || yes_flag || currfiles_prohash.tracked_file_names.len() == 0 {
+    if dry_run {
+        let mut plan = DryRunPlan {
+            deleted: currfiles_prohash
+                .tracked_file_names
+                .iter()
+                .map(|filename| utils::get_current_file_path(&app_paths, filename))
+                .collect(),
+            written: new_profile_entries
+                .iter()
+                .map(|entry| utils::get_current_file_path(&app_paths, &entry.name))
+                .collect(),
+            previous_profile: current_profile_names.first().cloned(),
+            confirmation_required: !(is_profile_saved
+                || yes_flag
+                || currfiles_prohash.tracked_file_names.is_empty()),
+            notes: vec![],
+        };
+
+        let ssh_config_path = utils::get_current_file_path(&app_paths, SSH_CONFIG_FILE_NAME);
+
+        if assemble_ssh_config {
+            if !plan.written.contains(&ssh_config_path) {
+                plan.written.push(ssh_config_path);
+            }
+            plan.notes
+                .push("~/.ssh/config would be assembled with the base config".to_string());
+        }
+
+        if settings.ssh_agent {
+            plan.notes
+                .push("the profile keys would be swapped in ssh-agent".to_string());
+        }
+
+        print_dry_run("use", &new_profile_name, plan, json_flag);
+        return Ok(());
+    }
+
+    if is_profile_saved || yes_flag || currfiles_prohash.tracked_file_names.is_empty() {
         return change_profile();
     };
 
+    if json_flag {
+        return Err(CliError::unsaved(format!(
+            "{}: The current files have not been saved or have been modified.\n\n{}",
+            APP_NAME, CONFIRMATION_REQUIRED_ERR
+        )));
+    }
+
     println!(
         "\ncurrent files ({}):",
         currfiles_prohash.tracked_file_names.len()
@@ -323,15 +1382,17 @@ pub fn use_profile(input_profile_name: &str, yes_flag: bool) -> Result<(), Strin
 
     let prompt = "The current files have not been saved or have been modified.\nThis action will delete them.\nAre you sure you want to proceed?";
 
-    if utils::confirm(prompt) {
-        return change_profile();
+    if confirm(prompt, json_flag)? {
+        change_profile()
     } else {
-        println!("\nProfile switch canceled.\n\n{}", profile_in_use_msg);
-        return Ok(());
+        Err(CliError::cancelled(format!(
+            "\nProfile switch canceled.\n\n{}",
+            profile_in_use_msg
+        )))
     }
 }
 
-pub fn discard_files(yes_flag: bool) -> Result<(), String> {
+pub fn discard_files(yes_flag: bool, json_flag: bool, dry_run: bool) -> Result<(), CliError> {
     let app_paths = utils::get_app_paths();
     let gitconfig_data = git::get_gitconfig_data(&app_paths.gitconfig_file_path);
 
@@ -340,11 +1401,11 @@ pub fn discard_files(yes_flag: bool) -> Result<(), String> {
 
     let currfiles_prohash = utils::get_profile_hash(&app_paths, gitconfig_data.file_exists, None)?;
 
-    if currfiles_prohash.tracked_file_names.len() == 0 {
-        return Err(format!(
+    if currfiles_prohash.tracked_file_names.is_empty() {
+        return Err(CliError::not_found(format!(
             "{}: Current files not found.\n\nNothing to discard.",
             APP_NAME
-        ));
+        )));
     }
 
     let mut is_profile_saved: bool = false;
@@ -361,7 +1422,10 @@ pub fn discard_files(yes_flag: bool) -> Result<(), String> {
         }
     }
 
-    let remove_current_files = || -> Result<(), String> {
+    let current_assembled_config = ssh_config::read_current_assembled(&app_paths);
+    let previous_profile_names = get_profiles_in_use(&app_paths);
+
+    let remove_current_files = || -> Result<(), CliError> {
         for filename in &currfiles_prohash.tracked_file_names {
             let file_to_remove_path = if filename == GITCONFIG_FILE_NAME {
                 app_paths.gitconfig_file_path.clone()
@@ -371,18 +1435,72 @@ pub fn discard_files(yes_flag: bool) -> Result<(), String> {
 
             if let Err(err) = fs::remove_file(file_to_remove_path) {
                 eprintln!("{}: Error: Could not remove file: {}\n", APP_NAME, filename);
-                return Err(err.to_string());
+                return Err(err.to_string().into());
             }
         }
 
-        println!("\nCurrent files discarded successfully!");
-        return Ok(());
+        // the base and unmanaged sections are not part of the current files
+        if let Some(assembled) = &current_assembled_config
+            && ssh_config::write_current_config(&app_paths, "", &assembled.unmanaged).is_err()
+        {
+            return Err(format!(
+                "{}: Error: Could not write file: {}",
+                APP_NAME, SSH_CONFIG_FILE_NAME
+            )
+            .into());
+        }
+
+        record_audit(&app_paths, "discard", "", &previous_profile_names);
+
+        if json_flag {
+            json::print_result(
+                "discard",
+                vec![(
+                    "files",
+                    Json::from(currfiles_prohash.tracked_file_names.clone()),
+                )],
+            );
+        } else {
+            println!("\nCurrent files discarded successfully!");
+        }
+        Ok(())
     };
 
+    if dry_run {
+        let mut plan = DryRunPlan {
+            deleted: currfiles_prohash
+                .tracked_file_names
+                .iter()
+                .map(|filename| utils::get_current_file_path(&app_paths, filename))
+                .collect(),
+            confirmation_required: !(is_profile_saved || yes_flag),
+            ..DryRunPlan::default()
+        };
+
+        if current_assembled_config.is_some() {
+            plan.written.push(utils::get_current_file_path(
+                &app_paths,
+                SSH_CONFIG_FILE_NAME,
+            ));
+            plan.notes
+                .push("the base and unmanaged sections of ~/.ssh/config would be kept".to_string());
+        }
+
+        print_dry_run("discard", "", plan, json_flag);
+        return Ok(());
+    }
+
     if is_profile_saved || yes_flag {
         return remove_current_files();
     }
 
+    if json_flag {
+        return Err(CliError::unsaved(format!(
+            "{}: The current files have not been saved or have been modified.\n\n{}",
+            APP_NAME, CONFIRMATION_REQUIRED_ERR
+        )));
+    }
+
     println!(
         "\ncurrent files ({}):",
         currfiles_prohash.tracked_file_names.len()
@@ -393,15 +1511,50 @@ pub fn discard_files(yes_flag: bool) -> Result<(), String> {
 
     let prompt = "The current files have not been saved or have been modified.\nThis action will delete them.\nAre you sure you want to proceed?";
 
-    if utils::confirm(prompt) {
-        return remove_current_files();
+    if confirm(prompt, json_flag)? {
+        remove_current_files()
     } else {
-        println!("\nNo files were discarded.");
-        return Ok(());
+        Err(CliError::cancelled(
+            "\nNo files were discarded.".to_string(),
+        ))
+    }
+}
+
+/// Prints every key of a layered profile's gitconfig with the layer it came from.
+fn print_gitconfig_layers(app_paths: &utils::AppPaths, profile_name: &str) {
+    let profile_path = app_paths.data_dir_path.join(profile_name);
+
+    let Ok(Some(base_content)) = store::read_base_gitconfig(&profile_path) else {
+        return;
+    };
+    let Some(base_name) = manifest::read_manifest(&profile_path).base else {
+        return;
+    };
+
+    let overlay_content =
+        fs::read_to_string(profile_path.join(GITCONFIG_FILE_NAME)).unwrap_or_default();
+    let overlay = git::parse_gitconfig(&overlay_content);
+    let merged = git::merge_gitconfig(&git::parse_gitconfig(&base_content), &overlay);
+
+    println!("  gitconfig layers (base: {}):", base_name);
+    for section in &merged {
+        for (key, value) in &section.entries {
+            let layer = if git::is_overlay_key(&overlay, &section.header, key) {
+                profile_name
+            } else {
+                base_name.as_str()
+            };
+
+            println!(
+                "    {:<40} {}",
+                format!("{} = {}", section.dotted_key(key), value),
+                layer
+            );
+        }
     }
 }
 
-pub fn list() -> Result<(), String> {
+pub fn list(json_flag: bool) -> Result<(), CliError> {
     let app_paths = utils::get_app_paths();
     let gitconfig_data = git::get_gitconfig_data(&app_paths.gitconfig_file_path);
 
@@ -411,27 +1564,113 @@ pub fn list() -> Result<(), String> {
     let currfiles_prohash = utils::get_profile_hash(&app_paths, gitconfig_data.file_exists, None)?;
 
     let mut current_profile_names: Vec<String> = vec![];
+    let mut profile_lines: Vec<String> = vec![];
+    let mut profiles_json: Vec<Json> = vec![];
+    let profile_aliases = aliases::read_aliases(&app_paths);
 
-    println!("\n[saved profiles: {}]", profile_dirs.len());
-
-    for profile_directory in profile_dirs {
+    for profile_directory in &profile_dirs {
         let mut prefix: &str = " ";
         let profile_prohash = utils::get_profile_hash(
             &app_paths,
             gitconfig_data.file_exists,
-            Some(&profile_directory),
+            Some(profile_directory),
         )?;
+        let in_use = currfiles_prohash.hash == profile_prohash.hash;
 
-        if currfiles_prohash.hash == profile_prohash.hash {
+        if in_use {
             prefix = "*";
             current_profile_names.push(profile_directory.clone());
         }
 
-        println!("{} {}", prefix, profile_directory);
+        let profile_path = app_paths.data_dir_path.join(profile_directory);
+        let is_sealed = store::is_sealed(&profile_path);
+        let unprotected_key_names = if is_sealed {
+            vec![]
+        } else {
+            keys::get_unprotected_key_names(&profile_path, &profile_prohash.tracked_file_names)
+        };
+        let alias_names = aliases::get_profile_aliases(&profile_aliases, profile_directory);
+        let mut tags: Vec<String> = vec![];
+
+        if is_sealed {
+            tags.push("sealed".to_string());
+        } else if !unprotected_key_names.is_empty() {
+            tags.push("unprotected keys".to_string());
+        }
+
+        if !alias_names.is_empty() {
+            tags.push(format!("alias: {}", alias_names.join(", ")));
+        }
+
+        if tags.is_empty() {
+            profile_lines.push(format!("{} {}", prefix, profile_directory));
+        } else {
+            profile_lines.push(format!(
+                "{} {} ({})",
+                prefix,
+                profile_directory,
+                tags.join(", ")
+            ));
+        }
+
+        profiles_json.push(Json::object([
+            ("name", Json::from(profile_directory)),
+            ("in_use", Json::from(in_use)),
+            ("sealed", Json::from(is_sealed)),
+            (
+                "base",
+                Json::from(manifest::read_manifest(&profile_path).base),
+            ),
+            ("files", Json::from(profile_prohash.tracked_file_names)),
+            ("unprotected_keys", Json::from(unprotected_key_names)),
+            ("aliases", Json::from(alias_names)),
+        ]));
+    }
+
+    let agent_identities =
+        agent::Agent::connect().and_then(|mut ssh_agent| ssh_agent.list_identities().ok());
+    let permission_fixes = utils::get_permission_fixes(&app_paths);
+
+    if json_flag {
+        let ssh_agent_keys = agent_identities.map(|identities| {
+            identities
+                .iter()
+                .map(|identity| {
+                    let algorithm = keys::get_key_blob_info(&identity.blob)
+                        .map(|info| keys::get_algorithm_label(&info.algorithm))
+                        .unwrap_or("UNKNOWN");
+
+                    Json::object([
+                        (
+                            "fingerprint",
+                            Json::from(keys::get_fingerprint(&identity.blob)),
+                        ),
+                        ("comment", Json::from(&identity.comment)),
+                        ("algorithm", Json::from(algorithm)),
+                    ])
+                })
+                .collect::<Vec<Json>>()
+        });
+
+        json::print_result(
+            "list",
+            vec![
+                ("profiles", Json::from(profiles_json)),
+                ("current", get_current_json(&app_paths)?),
+                ("ssh_agent_keys", Json::from(ssh_agent_keys)),
+                ("loose_permissions", Json::from(permission_fixes.len())),
+            ],
+        );
+        return Ok(());
+    }
+
+    println!("\n[saved profiles: {}]", profile_dirs.len());
+    for line in profile_lines {
+        println!("{}", line);
     }
-    println!("");
+    println!();
 
-    if currfiles_prohash.tracked_file_names.len() == 0 {
+    if currfiles_prohash.tracked_file_names.is_empty() {
         println!("--- No profile in use ---");
         println!("Current files (.gitconfig and/or SSH keys) not found.");
     } else if current_profile_names.is_empty() {
@@ -446,62 +1685,1908 @@ pub fn list() -> Result<(), String> {
         println!("  gitconfig email: {:?}", gitconfig_data.email);
     }
 
+    if let Some(current_profile_name) = current_profile_names.first() {
+        print_gitconfig_layers(&app_paths, current_profile_name);
+    }
+
     println!(
         "  current files ({}):",
         currfiles_prohash.tracked_file_names.len()
     );
+    let unprotected_key_names = keys::get_unprotected_key_names(
+        &app_paths.ssh_dir_path,
+        &currfiles_prohash.tracked_file_names,
+    );
+
     for filename in currfiles_prohash.tracked_file_names {
-        println!("    {}", filename);
+        if unprotected_key_names.contains(&filename) {
+            println!("    {}  (no passphrase)", filename);
+        } else {
+            println!("    {}", filename);
+        }
+    }
+
+    if let Some(identities) = agent_identities {
+        println!("  ssh-agent keys ({}):", identities.len());
+        for identity in identities {
+            let algorithm = keys::get_key_blob_info(&identity.blob)
+                .map(|info| keys::get_algorithm_label(&info.algorithm))
+                .unwrap_or("UNKNOWN");
+
+            println!(
+                "    {}  {} ({})",
+                keys::get_fingerprint(&identity.blob),
+                identity.comment,
+                algorithm
+            );
+        }
+    }
+
+    if !permission_fixes.is_empty() {
+        println!(
+            "\nWarning: {} files or directories have loose permissions.\nRun:\n    {} repair",
+            permission_fixes.len(),
+            APP_NAME
+        );
     }
 
     Ok(())
 }
 
-pub fn version() {
-    println!("{}", VERSION);
+/// Checks whether a profile is in use without printing anything, for prompts
+/// and scripts. Without `--is`, checks whether any saved profile is in use.
+pub fn status(is_profile_name: Option<&str>) -> Result<bool, CliError> {
+    let resolved_name = resolve_profile_name(is_profile_name.unwrap_or_default())?;
+    let profile_name = resolved_name.as_str();
+    let app_paths = utils::get_app_paths();
+
+    if !profile_name.is_empty() && !app_paths.data_dir_path.join(profile_name).is_dir() {
+        return Err(CliError::not_found(format!(
+            "{}: Profile {:?} not found.",
+            APP_NAME, profile_name
+        )));
+    }
+
+    let gitconfig_data = git::get_gitconfig_data(&app_paths.gitconfig_file_path);
+    let currfiles_prohash = utils::get_profile_hash(&app_paths, gitconfig_data.file_exists, None)?;
+
+    if currfiles_prohash.tracked_file_names.is_empty() {
+        return Ok(false);
+    }
+
+    let current_profile_names = get_current_profile_names(&app_paths, &currfiles_prohash)?;
+
+    if profile_name.is_empty() {
+        Ok(!current_profile_names.is_empty())
+    } else {
+        Ok(current_profile_names
+            .iter()
+            .any(|name| name == profile_name))
+    }
 }
 
-pub fn help() {
-    const HELP_TEXT: &str = r#"
-xks - Git profile switcher with SSH key management
+pub fn inherit(profile_name: &str, base_name: &str) -> Result<(), CliError> {
+    let resolved_name = resolve_full_profile_name(profile_name)?;
+    let profile_name = resolved_name.as_str();
+    let resolved_base_name = resolve_full_profile_name(base_name)?;
+    let base_name = resolved_base_name.as_str();
+    if profile_name.is_empty() {
+        let lines = [
+            format!("{}: Profile name cannot be empty.\n", APP_NAME),
+            format!(
+                "Examples:\n    {} inherit work common\n    {} inherit work",
+                APP_NAME, APP_NAME
+            ),
+        ];
+        let msg = lines.join("\n");
+        return Err(CliError::usage(msg));
+    }
 
-current_files: The default configuration files
-stored in their default locations:
-    ~/.gitconfig
-    ~/.ssh/config
-    ~/.ssh/id_ed25519  (also id_ed25519.pub)
-    ~/.ssh/id_ecdsa    (also id_ecdsa.pub)
-    ~/.ssh/id_rsa      (also id_rsa.pub)
-    ~/.ssh/id_dsa      (also id_dsa.pub)
+    let app_paths = utils::get_app_paths();
+    let profile_path = app_paths.data_dir_path.join(profile_name);
 
-xks only manages these files.
-    Other SSH keys or Git configuration files are ignored.
+    if !profile_path.is_dir() {
+        return Err(CliError::not_found(format!(
+            "{}: Profile {:?} not found.",
+            APP_NAME, profile_name
+        )));
+    }
 
-Usage:
-    xks <command> [options]
+    let mut profile_manifest = manifest::read_manifest(&profile_path);
 
-Commands:
-    save <profile>     Save current_files as a profile
-    use <profile>      Apply a saved profile
-    remove <profile>   Delete a saved profile
-    discard            Delete current_files
-    version            Show version number
-    help               Show this help message
+    // the whole .gitconfig as `use` writes it, before the layering changes
+    let full_gitconfig = if store::is_sealed(&profile_path) {
+        None
+    } else {
+        store::read_profile_entries(&app_paths, &profile_path, profile_name)?
+            .into_iter()
+            .find(|entry| entry.name == GITCONFIG_FILE_NAME)
+            .map(|entry| entry.content)
+    };
 
-Options:
-    -y                 Skip confirmation prompts
+    if base_name.is_empty() {
+        profile_manifest.base = None;
+    } else {
+        let base_path = app_paths.data_dir_path.join(base_name);
+        let layered_profiles =
+            manifest::get_layered_profiles(&app_paths.data_dir_path, profile_name);
+
+        if !base_path.is_dir() {
+            return Err(CliError::not_found(format!(
+                "{}: Profile {:?} not found.",
+                APP_NAME, base_name
+            )));
+        } else if base_name == profile_name {
+            return Err(format!("{}: A profile cannot inherit from itself.", APP_NAME).into());
+        } else if store::is_sealed(&profile_path) || store::is_sealed(&base_path) {
+            return Err(format!("{}: Sealed profiles cannot be layered.", APP_NAME).into());
+        } else if manifest::read_manifest(&base_path).base.is_some() {
+            return Err(format!(
+                "{}: Profile {:?} inherits from another profile and cannot be a base.",
+                APP_NAME, base_name
+            )
+            .into());
+        } else if !layered_profiles.is_empty() {
+            return Err(format!(
+                "{}: Profile {:?} is the base of: {}\n\nBase profiles cannot inherit.",
+                APP_NAME,
+                profile_name,
+                layered_profiles.join(", ")
+            )
+            .into());
+        }
 
-Examples:
-    xks                # List saved profiles and current_files state
-    xks save work      # Save current_files as 'work' profile
-    xks use personal   # Switch to 'personal' profile
-    xks use -          # Switch back to the previous profile
-    xks remove alex    # Delete 'alex' profile
-    xks discard        # Delete current_files
+        profile_manifest.base = Some(base_name.to_string());
+    }
 
-All data is stored in ~/.xks, including saved profiles.
+    if manifest::write_manifest(&profile_path, &profile_manifest).is_err() {
+        return Err(format!(
+            "{}: Error: Could not write file: {}",
+            APP_NAME, PROFILE_MANIFEST_FILE_NAME
+        )
+        .into());
+    }
 
-For more details: https://xks.rwx222.com
-"#;
-    println!("{}", HELP_TEXT);
+    // a layered profile stores only the keys that differ from its base, and
+    // the whole file to write back on use; a plain one stores the whole file
+    let write_err = |filename: &str| -> CliError {
+        format!("{}: Error: Could not write file: {}", APP_NAME, filename).into()
+    };
+    let full_gitconfig_path = profile_path.join(LAYERED_GITCONFIG_FILE_NAME);
+
+    if let Some(full_gitconfig) = &full_gitconfig {
+        match &profile_manifest.base {
+            Some(base) => {
+                let base_content = fs::read_to_string(
+                    app_paths.data_dir_path.join(base).join(GITCONFIG_FILE_NAME),
+                )
+                .unwrap_or_default();
+                let overlay = git::get_gitconfig_overlay(
+                    &git::parse_gitconfig(&base_content),
+                    &git::parse_gitconfig(&String::from_utf8_lossy(full_gitconfig)),
+                );
+
+                utils::write_tracked_file(
+                    profile_path.join(GITCONFIG_FILE_NAME),
+                    git::render_gitconfig(&overlay).as_bytes(),
+                )
+                .map_err(|_| write_err(GITCONFIG_FILE_NAME))?;
+                utils::write_tracked_file(&full_gitconfig_path, full_gitconfig)
+                    .map_err(|_| write_err(LAYERED_GITCONFIG_FILE_NAME))?;
+            }
+            None => {
+                utils::write_tracked_file(profile_path.join(GITCONFIG_FILE_NAME), full_gitconfig)
+                    .map_err(|_| write_err(GITCONFIG_FILE_NAME))?;
+                if full_gitconfig_path.exists() {
+                    fs::remove_file(&full_gitconfig_path)
+                        .map_err(|_| write_err(LAYERED_GITCONFIG_FILE_NAME))?;
+                }
+            }
+        }
+    }
+
+    match &profile_manifest.base {
+        Some(base) => println!(
+            "\nProfile {:?} now inherits its .gitconfig from {:?}.",
+            profile_name, base
+        ),
+        None => println!(
+            "\nProfile {:?} no longer inherits from a base profile.",
+            profile_name
+        ),
+    }
+
+    Ok(())
+}
+
+/// Reads a new passphrase, asked twice unless it comes from `$XKS_PASSPHRASE`.
+fn read_new_passphrase(prompt: &str, nothing_done_msg: &str) -> Result<String, CliError> {
+    let passphrase = utils::read_passphrase(prompt)?;
+
+    if env::var(PASSPHRASE_ENV_VAR).is_err() {
+        let passphrase_again = utils::read_passphrase("Repeat the passphrase: ")?;
+
+        if passphrase != passphrase_again {
+            return Err(format!(
+                "{}: Passphrases do not match.\n\n{}",
+                APP_NAME, nothing_done_msg
+            )
+            .into());
+        }
+    }
+
+    Ok(passphrase)
+}
+
+pub fn seal(profile_name: &str) -> Result<(), CliError> {
+    let resolved_name = resolve_full_profile_name(profile_name)?;
+    let profile_name = resolved_name.as_str();
+    if profile_name.is_empty() {
+        let lines = [
+            format!("{}: Profile name cannot be empty.\n", APP_NAME),
+            format!("Example:\n    {} seal alex", APP_NAME),
+        ];
+        let msg = lines.join("\n");
+        return Err(CliError::usage(msg));
+    }
+
+    let app_paths = utils::get_app_paths();
+    let profile_path = app_paths.data_dir_path.join(profile_name);
+
+    if !profile_path.is_dir() {
+        return Err(CliError::not_found(format!(
+            "{}: Profile {:?} not found.\n\nNo profile was sealed.",
+            APP_NAME, profile_name
+        )));
+    }
+
+    if store::is_sealed(&profile_path) {
+        return Err(format!(
+            "{}: Profile {:?} is already sealed.",
+            APP_NAME, profile_name
+        )
+        .into());
+    }
+
+    let layered_profiles = manifest::get_layered_profiles(&app_paths.data_dir_path, profile_name);
+
+    if manifest::read_manifest(&profile_path).base.is_some() || !layered_profiles.is_empty() {
+        return Err(format!(
+            "{}: Profile {:?} is layered, layered and base profiles cannot be sealed.",
+            APP_NAME, profile_name
+        )
+        .into());
+    }
+
+    let entries = store::read_plain_entries(&profile_path)?;
+
+    let prompt = format!("New passphrase for profile {:?}: ", profile_name);
+    let passphrase = read_new_passphrase(&prompt, "No profile was sealed.")?;
+
+    let seal_key = store::get_or_create_seal_key(&app_paths)?;
+    store::write_sealed(&profile_path, &entries, &passphrase, &seal_key)?;
+
+    for entry in &entries {
+        if let Err(err) = fs::remove_file(profile_path.join(&entry.name)) {
+            eprintln!(
+                "{}: Error: Could not remove file: {}\n",
+                APP_NAME, entry.name
+            );
+            return Err(err.to_string().into());
+        }
+    }
+
+    println!("\nProfile {:?} sealed successfully!", profile_name);
+    Ok(())
+}
+
+pub fn unseal(profile_name: &str) -> Result<(), CliError> {
+    let resolved_name = resolve_full_profile_name(profile_name)?;
+    let profile_name = resolved_name.as_str();
+    if profile_name.is_empty() {
+        let lines = [
+            format!("{}: Profile name cannot be empty.\n", APP_NAME),
+            format!("Example:\n    {} unseal alex", APP_NAME),
+        ];
+        let msg = lines.join("\n");
+        return Err(CliError::usage(msg));
+    }
+
+    let app_paths = utils::get_app_paths();
+    let profile_path = app_paths.data_dir_path.join(profile_name);
+
+    if !profile_path.is_dir() {
+        return Err(CliError::not_found(format!(
+            "{}: Profile {:?} not found.\n\nNo profile was unsealed.",
+            APP_NAME, profile_name
+        )));
+    }
+
+    if !store::is_sealed(&profile_path) {
+        return Err(format!("{}: Profile {:?} is not sealed.", APP_NAME, profile_name).into());
+    }
+
+    let entries = store::read_profile_entries(&app_paths, &profile_path, profile_name)?;
+
+    for entry in &entries {
+        if utils::write_tracked_file(profile_path.join(&entry.name), &entry.content).is_err() {
+            return Err(
+                format!("{}: Error: Could not write file: {}", APP_NAME, entry.name).into(),
+            );
+        }
+    }
+
+    if let Err(err) = fs::remove_file(profile_path.join(SEALED_FILE_NAME)) {
+        eprintln!(
+            "{}: Error: Could not remove file: {}\n",
+            APP_NAME, SEALED_FILE_NAME
+        );
+        return Err(err.to_string().into());
+    }
+
+    println!("\nProfile {:?} unsealed successfully!", profile_name);
+    Ok(())
+}
+
+pub fn show(profile_name: &str, json_flag: bool) -> Result<(), CliError> {
+    let resolved_name = resolve_profile_name(profile_name)?;
+    let profile_name = resolved_name.as_str();
+    if profile_name.is_empty() {
+        let lines = [
+            format!("{}: Profile name cannot be empty.\n", APP_NAME),
+            format!("Example:\n    {} show alex", APP_NAME),
+        ];
+        let msg = lines.join("\n");
+        return Err(CliError::usage(msg));
+    }
+
+    let app_paths = utils::get_app_paths();
+    let profile_path = app_paths.data_dir_path.join(profile_name);
+
+    if !profile_path.is_dir() {
+        return Err(CliError::not_found(format!(
+            "{}: Profile {:?} not found.",
+            APP_NAME, profile_name
+        )));
+    }
+
+    let gitconfig_data = git::get_gitconfig_data(&app_paths.gitconfig_file_path);
+    let currfiles_prohash = utils::get_profile_hash(&app_paths, gitconfig_data.file_exists, None)?;
+    let profile_prohash = utils::get_profile_hash(
+        &app_paths,
+        gitconfig_data.file_exists,
+        Some(&profile_name.to_string()),
+    )?;
+    let in_use = currfiles_prohash.hash == profile_prohash.hash;
+
+    // a sealed profile is listed from its index, it is never decrypted to be shown
+    if store::is_sealed(&profile_path) {
+        let index = store::read_sealed_index(&profile_path)?;
+        return print_sealed_show(profile_name, &index.tracked_file_names, in_use, json_flag);
+    }
+
+    let profile_manifest = manifest::read_manifest(&profile_path);
+
+    let entries = store::read_profile_entries(&app_paths, &profile_path, profile_name)?;
+    let file_names: Vec<String> = entries.iter().map(|entry| entry.name.clone()).collect();
+
+    if json_flag {
+        return print_show_json(&app_paths, profile_name, &entries, in_use);
+    }
+
+    println!("\n[profile: {}]", profile_name);
+
+    if in_use {
+        println!("  status: in use");
+    } else {
+        println!("  status: not in use");
+    }
+
+    match &profile_manifest.base {
+        Some(base) => println!("  manifest:\n    base = {}", base),
+        None => println!("  manifest: none"),
+    }
+
+    println!("  files ({}):", entries.len());
+    for entry in &entries {
+        let file_path = profile_path.join(&entry.name);
+        // a layered profile can get its whole .gitconfig from the base
+        let mode = utils::get_mode(&file_path)
+            .map(utils::format_mode)
+            .unwrap_or_else(|| "(base)".to_string());
+        let size = fs::metadata(&file_path)
+            .map(|metadata| metadata.len())
+            .unwrap_or_default();
+
+        println!("    {:<10} {:>6} B  {}", mode, size, entry.name);
+    }
+
+    if let Some(gitconfig_entry) = entries
+        .iter()
+        .find(|entry| entry.name == GITCONFIG_FILE_NAME)
+    {
+        let profile_gitconfig_data =
+            git::get_gitconfig_content_data(&String::from_utf8_lossy(&gitconfig_entry.content));
+
+        println!("  gitconfig:");
+        println!("    name:        {:?}", profile_gitconfig_data.name);
+        println!("    email:       {:?}", profile_gitconfig_data.email);
+
+        if !profile_gitconfig_data.signing_key.is_empty() {
+            println!("    signingkey:  {:?}", profile_gitconfig_data.signing_key);
+        }
+        if !profile_gitconfig_data.gpg_sign.is_empty() {
+            println!("    gpgsign:     {}", profile_gitconfig_data.gpg_sign);
+        }
+        if !profile_gitconfig_data.gpg_format.is_empty() {
+            println!("    gpg format:  {}", profile_gitconfig_data.gpg_format);
+        }
+    }
+
+    let key_names: Vec<&String> = file_names
+        .iter()
+        .filter(|filename| utils::is_private_key_file_name(filename))
+        .collect();
+
+    if !key_names.is_empty() {
+        println!("  ssh keys ({}):", key_names.len());
+    }
+
+    for filename in key_names {
+        let entry = entries.iter().find(|entry| &entry.name == filename);
+        let protection = entry
+            .map(|entry| keys::get_key_protection(&String::from_utf8_lossy(&entry.content)))
+            .unwrap_or(keys::KeyProtection::Unknown);
+        let algorithm = keys::get_private_key_info(&entries, filename)
+            .map(|info| {
+                format!(
+                    "{} {}",
+                    keys::get_algorithm_label(&info.algorithm),
+                    info.bits
+                )
+            })
+            .unwrap_or_else(|| "UNKNOWN".to_string());
+
+        println!(
+            "    {:<12}{:<13}{}  ({})",
+            filename,
+            algorithm,
+            get_key_file_fingerprint(&entries, filename),
+            protection.label()
+        );
+    }
+
+    let Some(config_entry) = entries
+        .iter()
+        .find(|entry| entry.name == SSH_CONFIG_FILE_NAME)
+    else {
+        println!("  ssh config: not saved in this profile");
+        return Ok(());
+    };
+
+    let ssh_config = ssh_config::parse_ssh_config(&String::from_utf8_lossy(&config_entry.content));
+    let host_blocks: Vec<&ssh_config::HostBlock> = ssh_config
+        .blocks
+        .iter()
+        .filter(|block| !block.keyword.is_empty())
+        .collect();
+
+    println!("  ssh config hosts ({}):", host_blocks.len());
+    for block in &ssh_config.blocks {
+        println!("    {}", block.label());
+
+        if let Some(host_name) = &block.host_name {
+            println!("      HostName      {}", host_name);
+        }
+        if let Some(user) = &block.user {
+            println!("      User          {}", user);
+        }
+        for identity_file in &block.identity_files {
+            println!("      IdentityFile  {}", identity_file);
+        }
+    }
+
+    if !ssh_config.includes.is_empty() {
+        println!("  ssh config includes:");
+        for include in &ssh_config.includes {
+            println!("    {}", include);
+        }
+    }
+
+    let warnings =
+        ssh_config::get_identity_file_warnings(&ssh_config, &file_names, &app_paths.ssh_dir_path);
+
+    if !warnings.is_empty() {
+        println!("\nWarning: missing identity files.");
+        for warning in warnings {
+            println!("  {}", warning);
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the details `show` lists as a JSON result.
+fn print_show_json(
+    app_paths: &utils::AppPaths,
+    profile_name: &str,
+    entries: &[store::FileEntry],
+    in_use: bool,
+) -> Result<(), CliError> {
+    let profile_path = app_paths.data_dir_path.join(profile_name);
+    let file_names: Vec<String> = entries.iter().map(|entry| entry.name.clone()).collect();
+
+    let files: Vec<Json> = entries
+        .iter()
+        .map(|entry| {
+            // a .gitconfig taken whole from the base has no mode
+            let mode =
+                utils::get_mode(profile_path.join(&entry.name)).map(|mode| format!("{:04o}", mode));
+
+            Json::object([
+                ("name", Json::from(&entry.name)),
+                ("mode", Json::from(mode)),
+                ("size", Json::from(entry.content.len())),
+            ])
+        })
+        .collect();
+
+    let gitconfig = entries
+        .iter()
+        .find(|entry| entry.name == GITCONFIG_FILE_NAME)
+        .map(|entry| {
+            let data = git::get_gitconfig_content_data(&String::from_utf8_lossy(&entry.content));
+            let optional = |value: String| (!value.is_empty()).then_some(value);
+
+            Json::object([
+                ("name", Json::from(data.name)),
+                ("email", Json::from(data.email)),
+                ("signingkey", Json::from(optional(data.signing_key))),
+                ("gpgsign", Json::from(optional(data.gpg_sign))),
+                ("gpg_format", Json::from(optional(data.gpg_format))),
+            ])
+        });
+
+    let ssh_keys: Vec<Json> = file_names
+        .iter()
+        .filter(|filename| utils::is_private_key_file_name(filename))
+        .map(|filename| {
+            let protection = entries
+                .iter()
+                .find(|entry| &entry.name == filename)
+                .map(|entry| keys::get_key_protection(&String::from_utf8_lossy(&entry.content)))
+                .unwrap_or(keys::KeyProtection::Unknown);
+            let key_info = keys::get_private_key_info(entries, filename);
+
+            Json::object([
+                ("name", Json::from(filename)),
+                (
+                    "algorithm",
+                    Json::from(
+                        key_info
+                            .as_ref()
+                            .map(|info| keys::get_algorithm_label(&info.algorithm)),
+                    ),
+                ),
+                ("bits", Json::from(key_info.map(|info| info.bits))),
+                (
+                    "fingerprint",
+                    Json::from(
+                        keys::get_private_key_blob(entries, filename)
+                            .map(|blob| keys::get_fingerprint(&blob)),
+                    ),
+                ),
+                ("protection", Json::from(protection.label())),
+            ])
+        })
+        .collect();
+
+    let mut warnings: Vec<String> = vec![];
+
+    let ssh_config_json = entries
+        .iter()
+        .find(|entry| entry.name == SSH_CONFIG_FILE_NAME)
+        .map(|entry| {
+            let config = ssh_config::parse_ssh_config(&String::from_utf8_lossy(&entry.content));
+            warnings = ssh_config::get_identity_file_warnings(
+                &config,
+                &file_names,
+                &app_paths.ssh_dir_path,
+            );
+
+            let hosts: Vec<Json> = config
+                .blocks
+                .iter()
+                .map(|block| {
+                    Json::object([
+                        ("label", Json::from(block.label())),
+                        ("host_name", Json::from(block.host_name.as_ref())),
+                        ("user", Json::from(block.user.as_ref())),
+                        ("identity_files", Json::from(block.identity_files.clone())),
+                    ])
+                })
+                .collect();
+
+            Json::object([
+                ("hosts", Json::from(hosts)),
+                ("includes", Json::from(config.includes)),
+            ])
+        });
+
+    json::print_result(
+        "show",
+        vec![
+            ("profile", Json::from(profile_name)),
+            ("in_use", Json::from(in_use)),
+            ("sealed", Json::from(false)),
+            (
+                "base",
+                Json::from(manifest::read_manifest(&profile_path).base),
+            ),
+            ("files", Json::from(files)),
+            ("gitconfig", Json::from(gitconfig)),
+            ("ssh_keys", Json::from(ssh_keys)),
+            ("ssh_config", Json::from(ssh_config_json)),
+            ("warnings", Json::from(warnings)),
+        ],
+    );
+
+    Ok(())
+}
+
+/// Shows a sealed profile from its index: the names of its files only, as
+/// their contents stay encrypted.
+fn print_sealed_show(
+    profile_name: &str,
+    file_names: &[String],
+    in_use: bool,
+    json_flag: bool,
+) -> Result<(), CliError> {
+    if json_flag {
+        let files: Vec<Json> = file_names
+            .iter()
+            .map(|filename| {
+                Json::object([
+                    ("name", Json::from(filename)),
+                    ("mode", Json::from(None::<String>)),
+                    ("size", Json::from(None::<usize>)),
+                ])
+            })
+            .collect();
+
+        json::print_result(
+            "show",
+            vec![
+                ("profile", Json::from(profile_name)),
+                ("in_use", Json::from(in_use)),
+                ("sealed", Json::from(true)),
+                ("base", Json::from(None::<String>)),
+                ("files", Json::from(files)),
+                ("gitconfig", Json::from(None::<Json>)),
+                ("ssh_keys", Json::from(Vec::<Json>::new())),
+                ("ssh_config", Json::from(None::<Json>)),
+                ("warnings", Json::from(Vec::<String>::new())),
+            ],
+        );
+
+        return Ok(());
+    }
+
+    println!("\n[profile: {}]", profile_name);
+
+    if in_use {
+        println!("  status: in use");
+    } else {
+        println!("  status: not in use");
+    }
+
+    println!("  storage: sealed");
+    println!("  manifest: none");
+
+    println!("  files ({}):", file_names.len());
+    for filename in file_names {
+        println!("    {:<10} {:>6}    {}", "(sealed)", "", filename);
+    }
+
+    println!(
+        "  The contents are encrypted, `{} unseal {}` decrypts them.",
+        APP_NAME, profile_name
+    );
+
+    Ok(())
+}
+
+/// Returns the fingerprint of a key file, the only way keys are shown.
+fn get_key_file_fingerprint(entries: &[store::FileEntry], filename: &str) -> String {
+    let blob = match filename.strip_suffix(".pub") {
+        Some(_) => entries
+            .iter()
+            .find(|entry| entry.name == filename)
+            .and_then(|entry| {
+                keys::get_public_key_line_blob(&String::from_utf8_lossy(&entry.content))
+            }),
+        None => keys::get_private_key_blob(entries, filename),
+    };
+
+    blob.map(|blob| keys::get_fingerprint(&blob))
+        .unwrap_or_else(|| "(fingerprint unavailable)".to_string())
+}
+
+/// Compares a profile with the current files or another profile. Sealed
+/// profiles are only decrypted with `decrypt_flag`.
+pub fn diff(
+    old_profile_name: &str,
+    new_profile_name: &str,
+    decrypt_flag: bool,
+) -> Result<(), CliError> {
+    let resolved_old_name = resolve_profile_name(old_profile_name)?;
+    let old_profile_name = resolved_old_name.as_str();
+    let resolved_new_name = resolve_profile_name(new_profile_name)?;
+    let new_profile_name = resolved_new_name.as_str();
+    if old_profile_name.is_empty() {
+        let lines = [
+            format!("{}: Profile name cannot be empty.\n", APP_NAME),
+            format!(
+                "Examples:\n    {} diff work\n    {} diff work personal",
+                APP_NAME, APP_NAME
+            ),
+        ];
+        let msg = lines.join("\n");
+        return Err(CliError::usage(msg));
+    }
+
+    let app_paths = utils::get_app_paths();
+
+    let read_entries = |profile_name: &str| -> Result<Vec<store::FileEntry>, CliError> {
+        let profile_path = app_paths.data_dir_path.join(profile_name);
+
+        if !profile_path.is_dir() {
+            return Err(CliError::not_found(format!(
+                "{}: Profile {:?} not found.",
+                APP_NAME, profile_name
+            )));
+        }
+
+        if store::is_sealed(&profile_path) && !decrypt_flag {
+            return Err(CliError::usage(format!(
+                "{}: Profile {:?} is sealed, its files are only compared with --decrypt.\n\nExample:\n    {} diff {} --decrypt",
+                APP_NAME, profile_name, APP_NAME, profile_name
+            )));
+        }
+
+        Ok(store::read_profile_entries(
+            &app_paths,
+            &profile_path,
+            profile_name,
+        )?)
+    };
+
+    let old_entries = read_entries(old_profile_name)?;
+
+    let (new_label, new_entries) = if new_profile_name.is_empty() {
+        let gitconfig_data = git::get_gitconfig_data(&app_paths.gitconfig_file_path);
+        let currfiles_prohash =
+            utils::get_profile_hash(&app_paths, gitconfig_data.file_exists, None)?;
+        let entries = store::read_current_entries(&app_paths, &currfiles_prohash)?;
+
+        ("current files", entries)
+    } else {
+        (new_profile_name, read_entries(new_profile_name)?)
+    };
+
+    let mut file_names: Vec<&String> = old_entries
+        .iter()
+        .chain(new_entries.iter())
+        .map(|entry| &entry.name)
+        .collect();
+    file_names.sort();
+    file_names.dedup();
+
+    let find = |entries: &'_ [store::FileEntry], filename: &str| {
+        entries
+            .iter()
+            .find(|entry| entry.name == filename)
+            .map(|entry| entry.content.clone())
+    };
+
+    let mut details: Vec<String> = vec![];
+
+    println!("\n[diff: {} -> {}]", old_profile_name, new_label);
+
+    for filename in file_names {
+        let old_content = find(&old_entries, filename);
+        let new_content = find(&new_entries, filename);
+
+        let status = match (&old_content, &new_content) {
+            (Some(old), Some(new)) if old == new => "same",
+            (Some(_), Some(_)) => "changed",
+            (Some(_), None) => "removed",
+            _ => "added",
+        };
+
+        println!("  {:<9}{}", status, filename);
+
+        if status == "same" {
+            continue;
+        }
+
+        details.push(String::new());
+
+        if filename == GITCONFIG_FILE_NAME || filename == SSH_CONFIG_FILE_NAME {
+            let old_text = String::from_utf8_lossy(old_content.as_deref().unwrap_or_default());
+            let new_text = String::from_utf8_lossy(new_content.as_deref().unwrap_or_default());
+
+            details.push(format!("--- {}/{}", old_profile_name, filename));
+            details.push(format!("+++ {}/{}", new_label, filename));
+            details.extend(diff::format_unified(&diff::diff_lines(
+                &old_text, &new_text,
+            )));
+        } else {
+            details.push(format!("{} (key fingerprints):", filename));
+
+            if old_content.is_some() {
+                details.push(format!(
+                    "- {}",
+                    get_key_file_fingerprint(&old_entries, filename)
+                ));
+            }
+            if new_content.is_some() {
+                details.push(format!(
+                    "+ {}",
+                    get_key_file_fingerprint(&new_entries, filename)
+                ));
+            }
+        }
+    }
+
+    if details.is_empty() {
+        println!("\nNo differences.");
+    }
+
+    for line in details {
+        println!("{}", line);
+    }
+
+    Ok(())
+}
+
+pub fn audit() -> Result<(), CliError> {
+    let app_paths = utils::get_app_paths();
+
+    let profile_dirs: Vec<String> =
+        utils::get_profile_dirs(&app_paths.data_dir_path).unwrap_or_else(|_| vec![]);
+
+    let mut unprotected_count: usize = 0;
+    let mut unprotected_profiles: Vec<String> = vec![];
+
+    println!("\n[saved profiles: {}]", profile_dirs.len());
+
+    for profile_directory in profile_dirs {
+        let profile_path = app_paths.data_dir_path.join(&profile_directory);
+
+        if store::is_sealed(&profile_path) {
+            println!("{}  (sealed, skipped)", profile_directory);
+            continue;
+        }
+
+        let key_names: Vec<String> = utils::get_files(&profile_path)?
+            .into_iter()
+            .filter(|filename| {
+                TRACKED_FILE_NAMES.contains(&filename.as_str())
+                    && utils::is_private_key_file_name(filename)
+            })
+            .collect();
+
+        println!("{}", profile_directory);
+
+        if key_names.is_empty() {
+            println!("    (no private keys)");
+        }
+
+        for filename in key_names {
+            let protection = keys::get_key_file_protection(profile_path.join(&filename));
+
+            if protection == keys::KeyProtection::Unprotected {
+                unprotected_count += 1;
+
+                if !unprotected_profiles.contains(&profile_directory) {
+                    unprotected_profiles.push(profile_directory.clone());
+                }
+            }
+
+            println!("    {:<16}{}", filename, protection.label());
+        }
+    }
+
+    if unprotected_count > 0 {
+        return Err(CliError::audit_findings(format!(
+            "\n{}: Found {} private keys without a passphrase in: {}",
+            APP_NAME,
+            unprotected_count,
+            unprotected_profiles.join(", ")
+        )));
+    }
+
+    println!("\nAll private keys are protected by a passphrase.");
+    Ok(())
+}
+
+pub fn repair(yes_flag: bool) -> Result<(), CliError> {
+    let app_paths = utils::get_app_paths();
+    let permission_fixes = utils::get_permission_fixes(&app_paths);
+
+    if permission_fixes.is_empty() {
+        println!("\nAll permissions are correct. Nothing to repair.");
+        return Ok(());
+    }
+
+    println!("\nloose permissions ({}):", permission_fixes.len());
+    for fix in &permission_fixes {
+        println!(
+            "  {:o} -> {:o}  {}",
+            fix.current_mode,
+            fix.expected_mode,
+            fix.path.display()
+        );
+    }
+
+    let repair_permissions = || -> Result<(), CliError> {
+        for fix in &permission_fixes {
+            if let Err(err) = utils::set_mode(&fix.path, fix.expected_mode) {
+                eprintln!(
+                    "{}: Error: Could not change permissions: {}\n",
+                    APP_NAME,
+                    fix.path.display()
+                );
+                return Err(err.to_string().into());
+            }
+        }
+
+        println!("\nPermissions repaired successfully!");
+        Ok(())
+    };
+
+    if yes_flag {
+        return repair_permissions();
+    }
+
+    let prompt = "This action will restrict these permissions to the owner.\nAre you sure you want to proceed?";
+
+    if utils::confirm(prompt) {
+        repair_permissions()
+    } else {
+        Err(CliError::cancelled(
+            "\nNo permissions were changed.".to_string(),
+        ))
+    }
+}
+
+/// Writes one or all saved profiles, as they are stored, to an archive,
+/// optionally encrypted with a passphrase.
+pub fn export(
+    profile_name: &str,
+    all_flag: bool,
+    output: Option<&str>,
+    encrypt_flag: bool,
+    yes_flag: bool,
+) -> Result<(), CliError> {
+    let example = format!(
+        "Examples:\n    {} export work -o work.xks\n    {} export --all -o profiles.xks --encrypt",
+        APP_NAME, APP_NAME
+    );
+
+    let output_path = match output {
+        Some(output) if !output.is_empty() => PathBuf::from(output),
+        _ => {
+            return Err(CliError::usage(format!(
+                "{}: The archive file is required, give it with -o <file>.\n\n{}",
+                APP_NAME, example
+            )));
+        }
+    };
+
+    let profile_given = !profile_name.is_empty();
+
+    if all_flag == profile_given {
+        return Err(CliError::usage(format!(
+            "{}: Give either a profile or --all.\n\n{}",
+            APP_NAME, example
+        )));
+    }
+
+    let app_paths = utils::get_app_paths();
+
+    let mut profile_names: Vec<String> = if all_flag {
+        utils::get_profile_dirs(&app_paths.data_dir_path).unwrap_or_default()
+    } else {
+        vec![resolve_profile_name(profile_name)?]
+    };
+
+    if profile_names.is_empty() {
+        return Err(CliError::not_found(format!(
+            "{}: No saved profiles to export.",
+            APP_NAME
+        )));
+    }
+
+    // layered profiles need their base, so bases are exported with them
+    let mut index = 0;
+    while index < profile_names.len() {
+        let profile_path = app_paths.data_dir_path.join(&profile_names[index]);
+
+        if !profile_path.is_dir() {
+            return Err(CliError::not_found(format!(
+                "{}: Profile {:?} not found.\n\nNothing was exported.",
+                APP_NAME, profile_names[index]
+            )));
+        }
+
+        if let Some(base_name) = manifest::read_manifest(&profile_path).base
+            && !profile_names.contains(&base_name)
+        {
+            if !utils::is_safe_profile_name(&base_name) {
+                return Err(CliError::from(format!(
+                    "{}: Error: Profile {:?} has an invalid base profile: {:?}\n\nNothing was exported.",
+                    APP_NAME, profile_names[index], base_name
+                )));
+            }
+
+            println!(
+                "Profile {:?} is layered on {:?}, which is exported too.",
+                profile_names[index], base_name
+            );
+            profile_names.push(base_name);
+        }
+
+        index += 1;
+    }
+
+    let mut files: Vec<archive::ArchiveFile> = vec![];
+    for profile_name in &profile_names {
+        files.extend(archive::read_profile_files(
+            &app_paths.data_dir_path,
+            profile_name,
+        )?);
+    }
+
+    if output_path.exists() && !yes_flag {
+        let prompt = format!(
+            "The file {} already exists and will be overwritten.\nAre you sure you want to proceed?",
+            output_path.display()
+        );
+
+        if !confirm(&prompt, false)? {
+            return Err(CliError::cancelled("\nNothing was exported.".to_string()));
+        }
+    }
+
+    let passphrase = if encrypt_flag {
+        Some(read_new_passphrase(
+            "New passphrase for the archive: ",
+            "Nothing was exported.",
+        )?)
+    } else {
+        None
+    };
+
+    let data = archive::encode_archive(&profile_names, &files, passphrase.as_deref())?;
+
+    // the archive holds private keys, whether it is encrypted or not
+    utils::write_file_with_mode(&output_path, &data, PRIVATE_KEY_MODE).map_err(|e| {
+        format!(
+            "{}: Error: Could not write file: {}\n\n{}",
+            APP_NAME,
+            output_path.display(),
+            e
+        )
+    })?;
+
+    println!(
+        "\nExported {} profile(s) to {}{}:",
+        profile_names.len(),
+        output_path.display(),
+        if encrypt_flag { ", encrypted" } else { "" }
+    );
+    for profile_name in &profile_names {
+        println!("  {}", profile_name);
+    }
+    if !encrypt_flag {
+        println!("\nThe archive holds private keys, keep it safe or use --encrypt.");
+    }
+
+    Ok(())
+}
+
+/// How an imported profile that already exists is handled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Conflict {
+    Rename,
+    Overwrite,
+    Skip,
+}
+
+/// Returns `<profile>-2`, `<profile>-3` and so on, the first name neither
+/// saved nor `taken`.
+fn get_free_profile_name(
+    app_paths: &utils::AppPaths,
+    profile_name: &str,
+    taken: &[String],
+) -> String {
+    (2..)
+        .map(|number| format!("{}-{}", profile_name, number))
+        .find(|name| !app_paths.data_dir_path.join(name).exists() && !taken.contains(name))
+        .unwrap_or_default()
+}
+
+pub fn import_archive(
+    archive_path: &str,
+    rename_flag: bool,
+    overwrite_flag: bool,
+    skip_flag: bool,
+) -> Result<(), CliError> {
+    let example = format!("Example:\n    {} import-archive profiles.xks", APP_NAME);
+
+    if archive_path.is_empty() {
+        return Err(CliError::usage(format!(
+            "{}: The archive file cannot be empty.\n\n{}",
+            APP_NAME, example
+        )));
+    }
+
+    let conflict_flag = match (rename_flag, overwrite_flag, skip_flag) {
+        (false, false, false) => None,
+        (true, false, false) => Some(Conflict::Rename),
+        (false, true, false) => Some(Conflict::Overwrite),
+        (false, false, true) => Some(Conflict::Skip),
+        _ => {
+            return Err(CliError::usage(format!(
+                "{}: Give only one of --rename, --overwrite and --skip.",
+                APP_NAME
+            )));
+        }
+    };
+
+    let archive_path = PathBuf::from(archive_path);
+    let data = fs::read(&archive_path).map_err(|e| {
+        let msg = format!(
+            "{}: Error: Could not read file: {}\n\n{}",
+            APP_NAME,
+            archive_path.display(),
+            e
+        );
+        match e.kind() {
+            ErrorKind::NotFound => CliError::not_found(msg),
+            _ => msg.into(),
+        }
+    })?;
+
+    let header = archive::read_archive_header(&data, &archive_path)?;
+    let passphrase = if header.is_encrypted() {
+        Some(utils::read_passphrase("Passphrase for the archive: ")?)
+    } else {
+        None
+    };
+    let files = archive::decode_archive(&data, &archive_path, passphrase.as_deref())?;
+
+    let app_paths = utils::get_app_paths();
+
+    for profile_name in &header.profile_names {
+        validate_profile_name(profile_name, example.clone())?;
+    }
+
+    let conflicts: Vec<&String> = header
+        .profile_names
+        .iter()
+        .filter(|profile_name| app_paths.data_dir_path.join(profile_name).exists())
+        .collect();
+
+    if !conflicts.is_empty() {
+        let conflict_names: Vec<&str> = conflicts.iter().map(|name| name.as_str()).collect();
+
+        if conflict_flag.is_none() && !io::stdin().is_terminal() {
+            return Err(CliError::usage(format!(
+                "{}: Profiles already saved: {}\n\nRun again with --rename, --overwrite or --skip.\n\nNo profile was imported.",
+                APP_NAME,
+                conflict_names.join(", ")
+            )));
+        }
+
+        println!("\nProfiles already saved: {}", conflict_names.join(", "));
+    }
+
+    // the profile names to import each archive profile as, None to skip it
+    let mut targets: Vec<(String, Option<String>, bool)> = vec![];
+
+    for profile_name in &header.profile_names {
+        if !conflicts.contains(&profile_name) {
+            targets.push((profile_name.clone(), Some(profile_name.clone()), false));
+            continue;
+        }
+
+        let conflict = match conflict_flag {
+            Some(conflict) => conflict,
+            None => {
+                if confirm(
+                    &format!("Replace the saved profile {:?}?", profile_name),
+                    false,
+                )? {
+                    Conflict::Overwrite
+                } else if confirm(
+                    &format!("Import {:?} under a new name instead?", profile_name),
+                    false,
+                )? {
+                    Conflict::Rename
+                } else {
+                    Conflict::Skip
+                }
+            }
+        };
+
+        let taken: Vec<String> = targets
+            .iter()
+            .filter_map(|(_, target_name, _)| target_name.clone())
+            .chain(header.profile_names.iter().cloned())
+            .collect();
+
+        match conflict {
+            Conflict::Skip => targets.push((profile_name.clone(), None, false)),
+            Conflict::Overwrite => {
+                targets.push((profile_name.clone(), Some(profile_name.clone()), true))
+            }
+            Conflict::Rename => {
+                let new_name = get_free_profile_name(&app_paths, profile_name, &taken);
+                validate_profile_name(&new_name, example.clone())?;
+                targets.push((profile_name.clone(), Some(new_name), false));
+            }
+        }
+    }
+
+    println!();
+
+    for (profile_name, target_name, overwrite) in &targets {
+        let Some(target_name) = target_name else {
+            println!("Profile {:?} skipped.", profile_name);
+            continue;
+        };
+
+        let profile_path = app_paths.data_dir_path.join(target_name);
+
+        if *overwrite {
+            trash::move_to_trash(&app_paths, target_name).map_err(|e| {
+                format!(
+                    "{}: Error: Could not move the profile to the trash: {}\n\n{}",
+                    APP_NAME, target_name, e
+                )
+            })?;
+        }
+
+        let write_err = |path: &Path, e: io::Error| -> CliError {
+            format!(
+                "{}: Error: Could not write file: {}\n\n{}",
+                APP_NAME,
+                path.display(),
+                e
+            )
+            .into()
+        };
+
+        utils::create_private_dir_all(&profile_path).map_err(|e| write_err(&profile_path, e))?;
+
+        for file in files
+            .iter()
+            .filter(|file| &file.profile_name == profile_name)
+        {
+            let file_path = profile_path.join(&file.name);
+
+            utils::write_file_with_mode(&file_path, &file.content, file.get_safe_mode())
+                .map_err(|e| write_err(&file_path, e))?;
+        }
+
+        // a renamed base keeps its layered profiles pointing at it
+        let mut profile_manifest = manifest::read_manifest(&profile_path);
+        if let Some(base_name) = &profile_manifest.base
+            && let Some((_, Some(new_base_name), _)) = targets
+                .iter()
+                .find(|(archive_name, _, _)| archive_name == base_name)
+            && new_base_name != base_name
+        {
+            profile_manifest.base = Some(new_base_name.clone());
+            manifest::write_manifest(&profile_path, &profile_manifest)
+                .map_err(|e| write_err(&profile_path, e))?;
+        }
+
+        if *overwrite {
+            println!(
+                "Profile {:?} imported, the one it replaced is in the trash.",
+                target_name
+            );
+        } else if target_name != profile_name {
+            println!("Profile {:?} imported as {:?}.", profile_name, target_name);
+        } else {
+            println!("Profile {:?} imported.", profile_name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists the profile switches, most recent first.
+pub fn history(json_flag: bool) -> Result<(), CliError> {
+    let app_paths = utils::get_app_paths();
+    let gitconfig_data = git::get_gitconfig_data(&app_paths.gitconfig_file_path);
+    let currfiles_prohash = utils::get_profile_hash(&app_paths, gitconfig_data.file_exists, None)?;
+    let current_profile_names = get_current_profile_names(&app_paths, &currfiles_prohash)?;
+
+    let entries = history::read_history(&app_paths);
+    let now = history::get_now();
+
+    if json_flag {
+        let entries_json: Vec<Json> = entries
+            .iter()
+            .rev()
+            .map(|entry| {
+                Json::object([
+                    ("profile", Json::from(&entry.profile_name)),
+                    ("timestamp", Json::from(entry.timestamp)),
+                    (
+                        "exists",
+                        Json::from(app_paths.data_dir_path.join(&entry.profile_name).is_dir()),
+                    ),
+                ])
+            })
+            .collect();
+
+        json::print_result(
+            "history",
+            vec![
+                ("entries", Json::from(entries_json)),
+                ("current_profiles", Json::from(current_profile_names)),
+            ],
+        );
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        println!(
+            "\nNo profile switches recorded yet.\nSwitches are recorded by:\n    {} use <profile>",
+            APP_NAME
+        );
+        return Ok(());
+    }
+
+    println!("\n[history: {}]", entries.len());
+
+    for (index, entry) in entries.iter().rev().enumerate() {
+        let prefix = if index == 0 && current_profile_names.contains(&entry.profile_name) {
+            "*"
+        } else {
+            " "
+        };
+        let removed = if app_paths.data_dir_path.join(&entry.profile_name).is_dir() {
+            ""
+        } else {
+            " (removed)"
+        };
+
+        println!(
+            "{} {}  {:<16} {}{}",
+            prefix,
+            history::format_timestamp(entry.timestamp),
+            history::format_age(entry.timestamp, now),
+            entry.profile_name,
+            removed
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints the audit log, most recent first, optionally only the entries since
+/// a date or a relative time.
+pub fn log(since: Option<&str>, json_flag: bool) -> Result<(), CliError> {
+    let app_paths = utils::get_app_paths();
+    let settings = settings::get_settings(&app_paths);
+    let now = history::get_now();
+
+    let since_timestamp = match since {
+        Some(since) => Some(history::parse_time(since, now).ok_or_else(|| {
+            CliError::usage(format!(
+                "{}: Unrecognized time {:?}.\n\nExamples:\n    {} log --since 2024-05-01\n    {} log --since 2.days.ago",
+                APP_NAME, since, APP_NAME, APP_NAME
+            ))
+        })?),
+        None => None,
+    };
+
+    let entries: Vec<audit::AuditEntry> = audit::read_entries(&app_paths, &settings)
+        .into_iter()
+        .filter(|entry| since_timestamp.is_none_or(|since| entry.timestamp >= since))
+        .collect();
+
+    if json_flag {
+        json::print_result(
+            "log",
+            vec![(
+                "entries",
+                Json::from(
+                    entries
+                        .iter()
+                        .rev()
+                        .map(audit::AuditEntry::to_json)
+                        .collect::<Vec<Json>>(),
+                ),
+            )],
+        );
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        if !settings.audit_log {
+            println!("\nThe audit log is turned off by the audit_log setting.");
+        } else if since.is_some() {
+            println!("\nNo audit log entries in that time.");
+        } else {
+            println!("\nNo audit log entries recorded yet.");
+        }
+        return Ok(());
+    }
+
+    println!("\n[log: {}]", entries.len());
+
+    let format_names = |names: &[String]| -> String {
+        if names.is_empty() {
+            "none".to_string()
+        } else {
+            names.join(" - ")
+        }
+    };
+
+    for entry in entries.iter().rev() {
+        println!(
+            "{}  {:<8} {:<16} {} -> {}  {}@{}",
+            history::format_timestamp(entry.timestamp),
+            entry.command,
+            if entry.profile.is_empty() {
+                "-"
+            } else {
+                entry.profile.as_str()
+            },
+            format_names(&entry.from),
+            format_names(&entry.to),
+            entry.user,
+            entry.host
+        );
+
+        if !entry.email.is_empty() {
+            println!("    email: {}", entry.email);
+        }
+        for (key_name, fingerprint) in &entry.keys {
+            println!("    key:   {:<12}{}", key_name, fingerprint);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn trash(
+    action: &str,
+    trash_name: &str,
+    older_than: Option<&str>,
+    yes_flag: bool,
+) -> Result<(), CliError> {
+    let app_paths = utils::get_app_paths();
+    let entries = trash::read_trash(&app_paths);
+    let now = history::get_now();
+
+    if older_than.is_some() && action != "empty" {
+        return Err(CliError::usage(format!(
+            "{}: Option --older-than only applies to: {} trash empty",
+            APP_NAME, APP_NAME
+        )));
+    }
+
+    match action {
+        "" | "list" => {
+            if entries.is_empty() {
+                println!("\nThe trash is empty.");
+                return Ok(());
+            }
+
+            println!("\n[trash: {}]", entries.len());
+            for entry in &entries {
+                println!(
+                    "  {}  {:<16} {}",
+                    history::format_timestamp(entry.timestamp),
+                    history::format_age(entry.timestamp, now),
+                    entry.get_trash_name()
+                );
+            }
+            Ok(())
+        }
+        "restore" => {
+            if trash_name.is_empty() {
+                return Err(CliError::usage(format!(
+                    "{}: Profile name cannot be empty.\n\nExample:\n    {} trash restore alex",
+                    APP_NAME, APP_NAME
+                )));
+            }
+
+            let Some(entry) = trash::find_entry(entries, trash_name) else {
+                return Err(CliError::not_found(format!(
+                    "{}: Profile {:?} not found in the trash.\n\nSee:\n    {} trash list",
+                    APP_NAME, trash_name, APP_NAME
+                )));
+            };
+
+            let profile_path = app_paths.data_dir_path.join(&entry.profile_name);
+
+            if profile_path.exists() {
+                return Err(format!(
+                    "{}: Profile {:?} already exists, rename or remove it first.\n\nNo profile was restored.",
+                    APP_NAME, entry.profile_name
+                )
+                .into());
+            }
+
+            fs::rename(&entry.path, &profile_path).map_err(|e| {
+                format!(
+                    "{}: Error: Could not restore the profile from: {}\n\n{}",
+                    APP_NAME,
+                    entry.path.display(),
+                    e
+                )
+            })?;
+
+            if let Some(base_name) = manifest::read_manifest(&profile_path).base
+                && !app_paths.data_dir_path.join(&base_name).is_dir()
+            {
+                eprintln!(
+                    "{}: Warning: The base profile {:?} no longer exists, restore it too or run:\n    {} inherit {}",
+                    APP_NAME, base_name, APP_NAME, entry.profile_name
+                );
+            }
+
+            println!("\nProfile {:?} restored successfully!", entry.profile_name);
+            Ok(())
+        }
+        "empty" => {
+            let cutoff = match older_than {
+                Some(older_than) => history::parse_time(older_than, now).ok_or_else(|| {
+                    CliError::usage(format!(
+                        "{}: Unrecognized age {:?}.\n\nExample:\n    {} trash empty --older-than 30d",
+                        APP_NAME, older_than, APP_NAME
+                    ))
+                })?,
+                None => u64::MAX,
+            };
+
+            let expired: Vec<&trash::TrashEntry> = entries
+                .iter()
+                .filter(|entry| entry.timestamp < cutoff)
+                .collect();
+
+            if expired.is_empty() {
+                println!("\nNo removed profiles to delete.");
+                return Ok(());
+            }
+
+            let prompt = format!(
+                "This action will permanently delete {} removed profile(s) and all their files.\nAre you sure you want to proceed?",
+                expired.len()
+            );
+
+            if !yes_flag && !confirm(&prompt, false)? {
+                return Err(CliError::cancelled(
+                    "\nThe trash was not emptied.".to_string(),
+                ));
+            }
+
+            for entry in &expired {
+                if let Err(err) = fs::remove_dir_all(&entry.path) {
+                    eprintln!("{}: {}\n", APP_NAME, REMOVING_DIR_ERR);
+                    return Err(err.to_string().into());
+                }
+            }
+
+            println!(
+                "\n{} removed profile(s) deleted permanently.",
+                expired.len()
+            );
+            Ok(())
+        }
+        _ => Err(CliError::usage(format!(
+            "{}: Unrecognized trash action {:?}, expected list, restore or empty.\n\nExample:\n    {} trash restore alex",
+            APP_NAME, action, APP_NAME
+        ))),
+    }
+}
+
+/// Lists, sets or removes the aliases of saved profiles.
+pub fn alias(action: &str, alias_name: &str, profile_name: &str) -> Result<(), CliError> {
+    let app_paths = utils::get_app_paths();
+    let mut profile_aliases = aliases::read_aliases(&app_paths);
+    let example = format!("Example:\n    {} alias set w work@acme.com", APP_NAME);
+
+    match action {
+        "" | "list" => {
+            if profile_aliases.is_empty() {
+                println!("\nNo aliases saved.\n\n{}", example);
+                return Ok(());
+            }
+
+            println!("\n[aliases: {}]", profile_aliases.len());
+            for (alias_name, profile_name) in &profile_aliases {
+                println!("  {} -> {}", alias_name, profile_name);
+            }
+            Ok(())
+        }
+        "set" => {
+            if alias_name.is_empty() || profile_name.is_empty() {
+                return Err(CliError::usage(format!(
+                    "{}: Alias and profile names cannot be empty.\n\n{}",
+                    APP_NAME, example
+                )));
+            }
+
+            validate_profile_name(alias_name, example)?;
+
+            if app_paths.data_dir_path.join(alias_name).is_dir() {
+                return Err(CliError::usage(format!(
+                    "{}: A profile named {:?} already exists, aliases cannot shadow profiles.",
+                    APP_NAME, alias_name
+                )));
+            }
+
+            let resolved_name = resolve_profile_name(profile_name)?;
+
+            if !app_paths.data_dir_path.join(&resolved_name).is_dir() {
+                return Err(CliError::not_found(format!(
+                    "{}: Profile {:?} not found.",
+                    APP_NAME, resolved_name
+                )));
+            }
+
+            profile_aliases.retain(|(existing, _)| existing != alias_name);
+            profile_aliases.push((alias_name.to_string(), resolved_name.clone()));
+            profile_aliases.sort();
+
+            aliases::write_aliases(&app_paths, &profile_aliases).map_err(|e| {
+                format!(
+                    "{}: Error: Could not write file: {}\n\n{}",
+                    APP_NAME,
+                    app_paths.aliases_file_path.display(),
+                    e
+                )
+            })?;
+
+            println!(
+                "\nAlias {:?} now points to profile {:?}.",
+                alias_name, resolved_name
+            );
+            Ok(())
+        }
+        "remove" => {
+            if !profile_aliases
+                .iter()
+                .any(|(existing, _)| existing == alias_name)
+            {
+                return Err(CliError::not_found(format!(
+                    "{}: Alias {:?} not found.",
+                    APP_NAME, alias_name
+                )));
+            }
+
+            profile_aliases.retain(|(existing, _)| existing != alias_name);
+
+            aliases::write_aliases(&app_paths, &profile_aliases).map_err(|e| {
+                format!(
+                    "{}: Error: Could not write file: {}\n\n{}",
+                    APP_NAME,
+                    app_paths.aliases_file_path.display(),
+                    e
+                )
+            })?;
+
+            println!("\nAlias {:?} removed successfully!", alias_name);
+            Ok(())
+        }
+        _ => Err(CliError::usage(format!(
+            "{}: Unrecognized alias action {:?}, expected list, set or remove.\n\n{}",
+            APP_NAME, action, example
+        ))),
+    }
+}
+
+/// Prints the completion script of `shell`.
+pub fn completions(shell: &str) -> Result<(), CliError> {
+    let Some(script) = completion::get_completion_script(shell) else {
+        return Err(CliError::usage(format!(
+            "{}: Unsupported shell {:?}, expected one of: {}.\n\n{}",
+            APP_NAME,
+            shell,
+            completion::SHELLS.join(", "),
+            HELP_LINE
+        )));
+    };
+
+    print!("{}", script);
+    Ok(())
+}
+
+/// Prints the completions of the last word in `words`, one per line. Used by
+/// the completion scripts, so errors print nothing.
+pub fn complete(words: &[String]) {
+    let app_paths = utils::get_app_paths();
+    let mut trashed_names: Vec<String> = vec![];
+
+    for entry in trash::read_trash(&app_paths) {
+        if !trashed_names.contains(&entry.profile_name) {
+            trashed_names.push(entry.profile_name.clone());
+        }
+        trashed_names.push(entry.get_trash_name());
+    }
+
+    let names = completion::CompletionNames {
+        profiles: utils::get_profile_dirs(&app_paths.data_dir_path).unwrap_or_default(),
+        aliases: aliases::read_aliases(&app_paths)
+            .into_iter()
+            .map(|(alias_name, _)| alias_name)
+            .collect(),
+        trashed: trashed_names,
+    };
+
+    for candidate in completion::get_candidates(words, &names) {
+        println!("{}", candidate);
+    }
+}
+
+pub fn version(json_flag: bool) {
+    if json_flag {
+        json::print_result("version", vec![("version", Json::from(VERSION))]);
+    } else {
+        println!("{}", VERSION);
+    }
+}
+
+/// Prints the help message, or the help generated for `command_name`.
+pub fn help(command_name: &str) -> Result<(), CliError> {
+    if !command_name.is_empty() {
+        let Some(command) = args::find_command(command_name) else {
+            return Err(CliError::usage(format!(
+                "{}: Unrecognized command {:?}.\n\n{}",
+                APP_NAME, command_name, HELP_LINE
+            )));
+        };
+
+        println!("\n{}\n", args::get_command_help(command));
+        return Ok(());
+    }
+
+    const HELP_TEXT: &str = r#"
+xks - Git profile switcher with SSH key management
+
+current_files: The default configuration files
+stored in their default locations:
+    ~/.gitconfig
+    ~/.ssh/config
+    ~/.ssh/id_ed25519  (also id_ed25519.pub)
+    ~/.ssh/id_ecdsa    (also id_ecdsa.pub)
+    ~/.ssh/id_rsa      (also id_rsa.pub)
+    ~/.ssh/id_dsa      (also id_dsa.pub)
+
+xks only manages these files.
+    Other SSH keys or Git configuration files are ignored.
+
+Usage:
+    xks <command> [options]
+
+Commands:
+    save <profile>     Save current_files as a profile
+    use [<profile>]    Apply a saved profile, without <profile> pick one
+                       from a list in a terminal
+    use -N             Go back N profiles in the history, -1 is like -
+    use @{<time>}      Apply the profile in use at that time, e.g.
+                       @{yesterday}, @{last-week}, @{3.days.ago}, @{2h}
+    remove <profile>   Move a saved profile to the trash
+    rename <old> <new> Rename a saved profile
+    copy <src> <dst>   Copy a saved profile to a new name
+    show <profile>     Show the files, identity, keys and hosts of a profile
+    diff <profile> [<other>]
+                       Compare a profile with current_files, or with
+                       another profile (keys show fingerprints only)
+    inherit <profile> [<base>]
+                       Layer a profile's .gitconfig on a base profile,
+                       without <base> the profile stops inheriting
+    discard            Delete current_files
+    status [--is <profile>]
+                       Print nothing, exit with 0 when a profile (or the
+                       given one) is in use and 8 otherwise
+    seal <profile>     Encrypt a saved profile with a passphrase
+    unseal <profile>   Decrypt a sealed profile back to plain files
+    audit              Report private keys saved without a passphrase
+    repair             Restrict permissions of keys and data directories
+    export <profile|--all> -o <file> [--encrypt]
+                       Write saved profiles to one archive, optionally
+                       encrypted with a passphrase
+    import-archive <file> [--rename|--overwrite|--skip]
+                       Add the profiles of an archive, renaming, replacing
+                       or skipping the ones already saved
+    history            List the profile switches, most recent first
+    log [--since <time>]
+                       List the audit log of use, save, remove and discard,
+                       since a date like 2024-05-01 or an age like 2.days.ago
+    trash [list]       List the removed profiles
+    trash restore <profile>
+                       Restore a removed profile
+    trash empty [--older-than <age>]
+                       Permanently delete the removed profiles, or only
+                       the ones removed longer ago, e.g. 30d
+    alias [list]       List the aliases of saved profiles
+    alias set <alias> <profile>
+                       Make <alias> a short name for a profile
+    alias remove <alias>
+                       Delete an alias
+    tui                Browse profiles in a full-screen terminal UI, and
+                       use, save, rename, remove or diff them
+    completions <shell>
+                       Print the completion script for bash, zsh or fish
+    version            Show version number
+    help [<command>]   Show this help message, or the help of a command
+
+Options:
+    -y, --yes          Skip confirmation prompts
+    --json             Print the result as JSON (list, save, use, remove,
+                       discard, show, history, log and version)
+    --dry-run          Print the files that would be deleted and written,
+                       and change nothing (use, save, remove and discard)
+    --wait             Wait for another running xks to finish, instead of
+                       failing (commands that change files)
+    -h, --help         Show the help of the command
+    --                 Treat the remaining arguments as positional
+Options go before or after the command, as --name value or --name=value.
+
+Examples:
+    xks                # List saved profiles and current_files state
+    xks save work      # Save current_files as 'work' profile
+    xks use personal   # Switch to 'personal' profile
+    xks use -          # Switch back to the previous profile
+    xks use work --dry-run   # Show what switching to 'work' would change
+    xks remove alex    # Move 'alex' profile to the trash
+    xks rename alex al # Rename 'alex' profile to 'al'
+    xks discard        # Delete current_files
+    xks diff work      # Compare 'work' profile with current_files
+    xks inherit work common  # Merge 'common' .gitconfig under 'work'
+    xks seal work      # Encrypt 'work' profile, asks for a passphrase
+    xks repair         # Set 0700 on ~/.ssh and ~/.xks, 0600 on private keys
+    xks alias set w work@acme.com  # Type 'xks use w' instead
+    xks use acm        # Use the only profile starting with 'acm',
+                       # or whose part after '@' does
+    xks export --all -o profiles.xks --encrypt  # Archive every profile
+    source <(xks completions bash)  # Enable completion in bash
+
+Exit codes:
+    0  Success
+    1  Error, such as a file that could not be read or written
+    2  Usage error: unknown command, missing or invalid arguments
+    3  Profile or current_files not found
+    4  Canceled at a confirmation prompt, or -y is required with --json
+    5  current_files have unsaved changes and -y is required with --json
+    6  Another xks is running, see --wait
+
+All data is stored in ~/.xks, including saved profiles.
+Settings are read from ~/.xks/.config/settings, e.g.:
+    refuse_weak_keys = true   # Refuse DSA and RSA keys under 2048 bits
+    ssh_agent = true          # Swap the profile keys in ssh-agent on use
+    ssh_agent_lifetime = 3600 # Seconds the added keys stay in ssh-agent
+    audit_log = false         # Stop writing ~/.xks/.config/audit.log
+    audit_log_max_size = 1048576  # Bytes before the log is rotated
+    audit_log_keep = 5        # Rotated logs kept
+A base SSH config shared by all profiles is read from
+~/.xks/.config/ssh_config and merged into ~/.ssh/config on use.
+Sealed profiles read the passphrase from $XKS_PASSPHRASE when it is set.
+
+For more details: https://xks.rwx222.com
+"#;
+    println!("{}", HELP_TEXT);
+    Ok(())
 }
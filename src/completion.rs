@@ -0,0 +1,187 @@
+use crate::args::{self, COMMANDS, CommandSpec};
+
+pub const SHELLS: [&str; 3] = ["bash", "zsh", "fish"];
+
+// every script passes the words after `xks`, up to the one being completed,
+// to `xks __complete` and offers what it prints, one candidate per line
+const BASH_SCRIPT: &str = r#"# xks completion for bash, load it with:
+#     source <(xks completions bash)
+
+_xks() {
+    local words cword
+    # COMP_WORDS splits `work@acme.com` at `@`, so the words are rebuilt
+    if declare -F _get_comp_words_by_ref >/dev/null; then
+        _get_comp_words_by_ref -n @=: words cword
+    else
+        local line="${COMP_LINE:0:COMP_POINT}"
+        read -ra words <<< "$line"
+        [[ "$line" == *[[:space:]] ]] && words+=("")
+        cword=$((${#words[@]} - 1))
+    fi
+
+    local IFS=$'\n'
+    COMPREPLY=($(xks __complete "${words[@]:1:cword}" 2>/dev/null))
+
+    # bash replaces only the part after the last break, e.g. after `@`
+    local cur="${words[cword]}"
+    local prefix="${cur%"${COMP_WORDS[COMP_CWORD]}"}"
+    [[ -n "$prefix" ]] && COMPREPLY=("${COMPREPLY[@]#"$prefix"}")
+}
+
+complete -F _xks xks
+"#;
+
+const ZSH_SCRIPT: &str = r#"#compdef xks
+# xks completion for zsh, load it after compinit with:
+#     source <(xks completions zsh)
+
+_xks() {
+    local -a candidates
+    candidates=("${(@f)$(xks __complete "${(@)words[2,CURRENT]}" 2>/dev/null)}")
+    compadd -a candidates
+}
+
+if [ "$funcstack[1]" = "_xks" ]; then
+    _xks "$@"
+else
+    compdef _xks xks
+fi
+"#;
+
+const FISH_SCRIPT: &str = r#"# xks completion for fish, install it with:
+#     xks completions fish > ~/.config/fish/completions/xks.fish
+
+function __xks_complete
+    set -l tokens (commandline -opc)
+    set -l current (commandline -ct)
+    xks __complete $tokens[2..-1] "$current" 2>/dev/null
+end
+
+complete -c xks -f -a '(__xks_complete)'
+"#;
+
+pub fn get_completion_script(shell: &str) -> Option<&'static str> {
+    match shell {
+        "bash" => Some(BASH_SCRIPT),
+        "zsh" => Some(ZSH_SCRIPT),
+        "fish" => Some(FISH_SCRIPT),
+        _ => None,
+    }
+}
+
+/// The names offered for profile arguments.
+pub struct CompletionNames {
+    pub profiles: Vec<String>,
+    pub aliases: Vec<String>,
+    /// The removed profiles, by profile name and by trash name.
+    pub trashed: Vec<String>,
+}
+
+/// Returns the candidates of a positional argument, by its name in the
+/// command spec.
+fn get_argument_candidates(
+    command: &CommandSpec,
+    argument: &str,
+    names: &CompletionNames,
+) -> Vec<String> {
+    match argument.trim_matches(['[', ']']) {
+        "<action>" => match command.name {
+            "trash" => vec!["list", "restore", "empty"],
+            _ => vec!["list", "set", "remove"],
+        }
+        .iter()
+        .map(|action| action.to_string())
+        .collect(),
+        "<alias>" => names.aliases.clone(),
+        "<trashed>" => names.trashed.clone(),
+        "<shell>" => SHELLS.iter().map(|shell| shell.to_string()).collect(),
+        "<command>" => COMMANDS
+            .iter()
+            .map(|command| command.name.to_string())
+            .collect(),
+        // new names are not completed
+        "<new>" | "<dst>" | "<file>" => vec![],
+        _ => [names.profiles.as_slice(), names.aliases.as_slice()].concat(),
+    }
+}
+
+fn get_option_names(command: Option<&CommandSpec>) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+
+    for option in args::get_command_options(command) {
+        if let Some(short) = option.short {
+            names.push(format!("-{}", short));
+        }
+        names.push(format!("--{}", option.name));
+    }
+
+    names
+}
+
+/// Returns the completions of the last word in `words`, the arguments after
+/// `xks` up to the word under the cursor.
+pub fn get_candidates(words: &[String], names: &CompletionNames) -> Vec<String> {
+    let (current, previous) = match words.split_last() {
+        Some((current, previous)) => (current.as_str(), previous),
+        None => ("", &[] as &[String]),
+    };
+
+    let mut command: Option<&CommandSpec> = None;
+    let mut positional_count = 0;
+    let mut takes_value = false;
+    let mut value_name: Option<&str> = None;
+
+    for word in previous {
+        if takes_value {
+            takes_value = false;
+            continue;
+        }
+
+        if let Some(name) = word.strip_prefix("--")
+            && !name.contains('=')
+            && let Some(option) = args::get_command_options(command)
+                .into_iter()
+                .find(|option| option.name == name)
+            && option.value_name.is_some()
+        {
+            takes_value = true;
+            value_name = option.value_name;
+            continue;
+        }
+
+        if word.starts_with('-') && word.len() > 1 {
+            continue;
+        }
+
+        match command {
+            None => command = args::find_command(word),
+            Some(_) => positional_count += 1,
+        }
+    }
+
+    let candidates: Vec<String> = if takes_value {
+        match value_name {
+            Some("<profile>") => [names.profiles.as_slice(), names.aliases.as_slice()].concat(),
+            _ => vec![],
+        }
+    } else if current.starts_with('-') {
+        get_option_names(command)
+    } else {
+        match command {
+            None => COMMANDS
+                .iter()
+                .map(|command| command.name.to_string())
+                .collect(),
+            Some(command) => command
+                .arguments
+                .get(positional_count)
+                .map(|argument| get_argument_candidates(command, argument, names))
+                .unwrap_or_default(),
+        }
+    };
+
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(current))
+        .collect()
+}
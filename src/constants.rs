@@ -6,9 +6,10 @@ pub const CONFIG_DIR_NAME: &str = ".config";
 
 pub const SSH_DIR: &str = ".ssh";
 pub const GITCONFIG_FILE_NAME: &str = ".gitconfig";
+pub const SSH_CONFIG_FILE_NAME: &str = "config";
 pub const TRACKED_FILE_NAMES: [&str; 10] = [
     GITCONFIG_FILE_NAME,
-    "config",
+    SSH_CONFIG_FILE_NAME,
     "id_ed25519",
     "id_ed25519.pub",
     "id_ecdsa",
@@ -20,8 +21,23 @@ pub const TRACKED_FILE_NAMES: [&str; 10] = [
 ];
 
 pub const PREVIOUS_PROFILE_FILE_NAME: &str = "previous_profile";
+pub const SETTINGS_FILE_NAME: &str = "settings";
+pub const ALIASES_FILE_NAME: &str = "aliases";
+pub const HISTORY_FILE_NAME: &str = "history";
+pub const LOCK_FILE_NAME: &str = "lock";
+pub const AUDIT_LOG_FILE_NAME: &str = "audit.log";
+pub const TRASH_DIR_NAME: &str = "trash";
+pub const SEAL_KEY_FILE_NAME: &str = "seal_key";
+pub const BASE_SSH_CONFIG_FILE_NAME: &str = "ssh_config";
+pub const SEALED_FILE_NAME: &str = ".sealed";
+pub const PROFILE_MANIFEST_FILE_NAME: &str = ".manifest";
+// the whole `.gitconfig` of a layered profile as saved, comments included
+pub const LAYERED_GITCONFIG_FILE_NAME: &str = ".gitconfig.full";
 
-pub const YES_FLAG: &str = "-y";
+pub const PASSPHRASE_ENV_VAR: &str = "XKS_PASSPHRASE";
+pub const SSH_AUTH_SOCK_ENV_VAR: &str = "SSH_AUTH_SOCK";
+
+pub const JSON_SCHEMA_VERSION: u32 = 1;
 
 pub const TOGGLE_PREV: &str = "-";
 
@@ -29,8 +45,32 @@ pub const HELP_LINE: &str = "See:\n    xks help";
 
 pub const PROFILE_NAME_MAX_LENGTH: usize = 50;
 
+pub const HISTORY_MAX_ENTRIES: usize = 200;
+pub const AUDIT_LOG_MAX_SIZE: u64 = 1024 * 1024;
+pub const AUDIT_LOG_KEEP: usize = 5;
+
+pub const MIN_RSA_BITS: u32 = 2048;
+
+pub const PRIVATE_DIR_MODE: u32 = 0o700;
+pub const PRIVATE_KEY_MODE: u32 = 0o600;
+
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_NOT_FOUND: i32 = 3;
+pub const EXIT_CANCELLED: i32 = 4;
+pub const EXIT_UNSAVED: i32 = 5;
+pub const EXIT_LOCKED: i32 = 6;
+// `xks audit` exits with this when it finds keys without a passphrase
+pub const EXIT_AUDIT_FINDINGS: i32 = 7;
+// `xks status` exits with this when the profile is not in use
+pub const EXIT_NOT_IN_USE: i32 = 8;
+
 pub const REMOVING_DIR_ERR: &str =
     "Error: Could not remove directory. This may be due to insufficient permissions.";
 pub const READING_DIR_ERR: &str =
     "Error: Could not read directory. This may be due to insufficient permissions.";
 pub const READING_HASH_FILES_ERR: &str = "Error: Could not get files hash.";
+pub const CONFIRMATION_REQUIRED_ERR: &str =
+    "Error: Confirmation required, run again with -y to proceed.";
+pub const WRONG_PASSPHRASE_ERR: &str =
+    "Error: Wrong passphrase, or the sealed profile has been tampered with.";
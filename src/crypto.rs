@@ -0,0 +1,171 @@
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fs::File;
+use std::io::{self, Read};
+
+pub const KEY_LEN: usize = 32;
+pub const SALT_LEN: usize = 16;
+pub const NONCE_LEN: usize = 24;
+/// The count is written in every header, so tests can use a cheaper one.
+pub const PBKDF2_ITERATIONS: u32 = if cfg!(test) { 1_000 } else { 600_000 };
+/// Headers asking for more are rejected, so a modified file cannot make
+/// opening it hang.
+pub const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+
+/// Derives an encryption key from a passphrase with PBKDF2-HMAC-SHA256.
+pub fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; KEY_LEN] {
+    let mut key = [0u8; KEY_LEN];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+/// Encrypts `plaintext` with XChaCha20-Poly1305, authenticating `aad` too.
+/// The tag is appended to the returned ciphertext.
+pub fn seal(key: &[u8; KEY_LEN], nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    XChaCha20Poly1305::new(key.into())
+        .encrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .expect("encrypting into memory cannot fail")
+}
+
+/// Decrypts what `seal` returned. `None` when the key is wrong, or when the
+/// ciphertext or `aad` have been modified.
+pub fn open(key: &[u8; KEY_LEN], nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .ok()
+}
+
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Checks `tag` against the HMAC-SHA256 of `data` in constant time.
+pub fn verify_hmac_sha256(key: &[u8], data: &[u8], tag: &[u8]) -> bool {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.verify_slice(tag).is_ok()
+}
+
+pub fn random_bytes(len: usize) -> io::Result<Vec<u8>> {
+    let mut buffer = vec![0u8; len];
+    File::open("/dev/urandom")?.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONCE: [u8; NONCE_LEN] = [7; NONCE_LEN];
+
+    fn derive_key_hex(passphrase: &str, salt: &str, iterations: u32) -> String {
+        to_hex(&derive_key(passphrase, salt.as_bytes(), iterations))
+    }
+
+    // RFC 4231, test cases 1 and 2
+    #[test]
+    fn hmac_sha256_matches_rfc_4231() {
+        assert_eq!(
+            to_hex(&hmac_sha256(&[0x0b; 20], b"Hi There")),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            to_hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn verify_hmac_sha256_checks_key_data_and_tag() {
+        let tag = hmac_sha256(b"key", b"data");
+
+        assert!(verify_hmac_sha256(b"key", b"data", &tag));
+        assert!(!verify_hmac_sha256(b"other key", b"data", &tag));
+        assert!(!verify_hmac_sha256(b"key", b"other data", &tag));
+        assert!(!verify_hmac_sha256(b"key", b"data", &tag[1..]));
+    }
+
+    // the RFC 6070 inputs with SHA-256
+    #[test]
+    fn derive_key_matches_known_vectors() {
+        assert_eq!(
+            derive_key_hex("password", "salt", 1),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            derive_key_hex("password", "salt", 2),
+            "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"
+        );
+        assert_eq!(
+            derive_key_hex("password", "salt", 4096),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+
+    #[test]
+    fn sealed_data_opens_with_the_same_key_and_aad() {
+        let key = derive_key("passphrase", b"salt", 1);
+        let ciphertext = seal(&key, &NONCE, b"header", b"a message");
+
+        assert_ne!(&ciphertext[..9], b"a message");
+        assert_eq!(
+            open(&key, &NONCE, b"header", &ciphertext),
+            Some(b"a message".to_vec())
+        );
+    }
+
+    #[test]
+    fn sealed_data_rejects_any_change() {
+        let key = derive_key("passphrase", b"salt", 1);
+        let ciphertext = seal(&key, &NONCE, b"header", b"a message");
+
+        let other_key = derive_key("other passphrase", b"salt", 1);
+        assert_eq!(open(&other_key, &NONCE, b"header", &ciphertext), None);
+        assert_eq!(open(&key, &NONCE, b"headeR", &ciphertext), None);
+
+        let mut modified = ciphertext.clone();
+        modified[0] ^= 0x01;
+        assert_eq!(open(&key, &NONCE, b"header", &modified), None);
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        assert_eq!(to_hex(&[0x00, 0x7f, 0xff]), "007fff");
+        assert_eq!(from_hex("007fff"), Some(vec![0x00, 0x7f, 0xff]));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+}
@@ -0,0 +1,243 @@
+const CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineChange {
+    Same,
+    Removed,
+    Added,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffLine<'a> {
+    pub change: LineChange,
+    /// The line with its newline, if it has one.
+    pub text: &'a str,
+    old_number: usize,
+    new_number: usize,
+}
+
+/// Computes a line diff from the longest common subsequence of both texts.
+/// Profile files are small, so the quadratic table is fine. A last line
+/// without a newline differs from the same line with one, as in diff -u.
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let (n, m) = (old_lines.len(), new_lines.len());
+
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old_lines[i] == new_lines[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines: Vec<DiffLine> = vec![];
+    let (mut i, mut j) = (0, 0);
+
+    while i < n || j < m {
+        let (change, text) = if i < n && j < m && old_lines[i] == new_lines[j] {
+            (LineChange::Same, old_lines[i])
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            // removals come first within a change, as in diff -u
+            (LineChange::Removed, old_lines[i])
+        } else {
+            (LineChange::Added, new_lines[j])
+        };
+
+        lines.push(DiffLine {
+            change,
+            text,
+            old_number: i + 1,
+            new_number: j + 1,
+        });
+
+        match change {
+            LineChange::Same => {
+                i += 1;
+                j += 1;
+            }
+            LineChange::Removed => i += 1,
+            LineChange::Added => j += 1,
+        }
+    }
+
+    lines
+}
+
+/// Renders the changed lines as unified diff hunks, with a few lines of
+/// context around each change.
+pub fn format_unified(lines: &[DiffLine]) -> Vec<String> {
+    let changed: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.change != LineChange::Same)
+        .map(|(index, _)| index)
+        .collect();
+
+    let mut output: Vec<String> = vec![];
+    let mut position = 0;
+
+    while position < changed.len() {
+        let start = changed[position].saturating_sub(CONTEXT_LINES);
+        let mut end = changed[position];
+
+        while position < changed.len() && changed[position] <= end + 2 * CONTEXT_LINES {
+            end = changed[position];
+            position += 1;
+        }
+
+        let end = (end + CONTEXT_LINES + 1).min(lines.len());
+        let hunk = &lines[start..end];
+
+        let old_count = hunk
+            .iter()
+            .filter(|l| l.change != LineChange::Added)
+            .count();
+        let new_count = hunk
+            .iter()
+            .filter(|l| l.change != LineChange::Removed)
+            .count();
+
+        // an empty side starts at the line before the hunk, as in diff -u
+        let start_number = |number: usize, count: usize| {
+            if count == 0 {
+                number.saturating_sub(1)
+            } else {
+                number
+            }
+        };
+
+        output.push(format!(
+            "@@ -{},{} +{},{} @@",
+            start_number(hunk[0].old_number, old_count),
+            old_count,
+            start_number(hunk[0].new_number, new_count),
+            new_count
+        ));
+
+        for line in hunk {
+            let prefix = match line.change {
+                LineChange::Same => ' ',
+                LineChange::Removed => '-',
+                LineChange::Added => '+',
+            };
+            match line.text.strip_suffix('\n') {
+                Some(text) => output.push(format!("{}{}", prefix, text)),
+                None => {
+                    output.push(format!("{}{}", prefix, line.text));
+                    output.push("\\ No newline at end of file".to_string());
+                }
+            }
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unified(old: &str, new: &str) -> Vec<String> {
+        format_unified(&diff_lines(old, new))
+    }
+
+    /// Numbers the lines 1 to `count`, with some of them replaced.
+    fn numbered(count: usize, replaced: &[(usize, &str)]) -> String {
+        (1..=count)
+            .map(|n| match replaced.iter().find(|(number, _)| *number == n) {
+                Some((_, text)) => format!("{}\n", text),
+                None => format!("{}\n", n),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_texts_have_no_hunks() {
+        assert!(unified("", "").is_empty());
+        assert!(unified("a\nb\n", "a\nb\n").is_empty());
+        assert!(unified("a\nb", "a\nb").is_empty());
+    }
+
+    #[test]
+    fn changed_line_is_removed_then_added() {
+        assert_eq!(
+            unified("a\nb\nc\n", "a\nB\nc\n"),
+            ["@@ -1,3 +1,3 @@", " a", "-b", "+B", " c"]
+        );
+        assert_eq!(
+            unified("a\nb\nc\n", "x\ny\nc\n"),
+            ["@@ -1,3 +1,3 @@", "-a", "-b", "+x", "+y", " c"]
+        );
+    }
+
+    #[test]
+    fn added_and_removed_files() {
+        assert_eq!(unified("", "a\nb\n"), ["@@ -0,0 +1,2 @@", "+a", "+b"]);
+        assert_eq!(unified("a\n", ""), ["@@ -1,1 +0,0 @@", "-a"]);
+    }
+
+    #[test]
+    fn missing_newline_at_end_is_shown() {
+        assert_eq!(
+            unified("a\nb", "a\nb\n"),
+            [
+                "@@ -1,2 +1,2 @@",
+                " a",
+                "-b",
+                "\\ No newline at end of file",
+                "+b"
+            ]
+        );
+        assert_eq!(
+            unified("a\n", "a\nb"),
+            [
+                "@@ -1,1 +1,2 @@",
+                " a",
+                "+b",
+                "\\ No newline at end of file"
+            ]
+        );
+    }
+
+    #[test]
+    fn distant_changes_get_their_own_hunks() {
+        assert_eq!(
+            unified(
+                &numbered(20, &[]),
+                &numbered(20, &[(2, "two"), (19, "nineteen")])
+            ),
+            [
+                "@@ -1,5 +1,5 @@",
+                " 1",
+                "-2",
+                "+two",
+                " 3",
+                " 4",
+                " 5",
+                "@@ -16,5 +16,5 @@",
+                " 16",
+                " 17",
+                " 18",
+                "-19",
+                "+nineteen",
+                " 20"
+            ]
+        );
+    }
+
+    #[test]
+    fn close_changes_share_a_hunk() {
+        let output = unified(
+            &numbered(10, &[]),
+            &numbered(10, &[(3, "three"), (8, "eight")]),
+        );
+
+        assert_eq!(output[0], "@@ -1,10 +1,10 @@");
+        assert_eq!(output.len(), 13);
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 
@@ -6,40 +7,258 @@ pub struct GitConfigData {
     pub name: String,
     pub email: String,
     pub file_exists: bool,
+    pub signing_key: String,
+    pub gpg_sign: String,
+    pub gpg_format: String,
 }
 
 pub fn get_gitconfig_data<T: AsRef<Path>>(path: T) -> GitConfigData {
+    match fs::read_to_string(&path) {
+        Ok(content) => get_gitconfig_content_data(&content),
+        Err(_) => GitConfigData {
+            name: String::new(),
+            email: String::new(),
+            file_exists: false,
+            signing_key: String::new(),
+            gpg_sign: String::new(),
+            gpg_format: String::new(),
+        },
+    }
+}
+
+pub fn get_gitconfig_content_data(content: &str) -> GitConfigData {
     let mut name = String::new();
     let mut email = String::new();
-    let mut file_exists: bool = false;
 
-    if let Ok(content) = fs::read_to_string(&path) {
-        file_exists = true;
+    let mut in_user_section = false;
 
-        let mut in_user_section = false;
+    for line in content.lines() {
+        let trimmed = line.trim();
 
-        for line in content.lines() {
-            let trimmed = line.trim();
-
-            if trimmed == "[user]" {
-                in_user_section = true;
-            } else if trimmed.starts_with('[') {
-                in_user_section = false;
-            }
+        if trimmed == "[user]" {
+            in_user_section = true;
+        } else if trimmed.starts_with('[') {
+            in_user_section = false;
+        }
 
-            if in_user_section {
-                if trimmed.starts_with("name =") {
-                    name = trimmed.split('=').nth(1).unwrap().trim().to_string();
-                } else if trimmed.starts_with("email =") {
-                    email = trimmed.split('=').nth(1).unwrap().trim().to_string();
-                }
+        if in_user_section {
+            if trimmed.starts_with("name =") {
+                name = trimmed.split('=').nth(1).unwrap().trim().to_string();
+            } else if trimmed.starts_with("email =") {
+                email = trimmed.split('=').nth(1).unwrap().trim().to_string();
             }
         }
     }
 
+    let sections = parse_gitconfig(content);
+    let get_value = |header: &str, key: &str| {
+        sections
+            .iter()
+            .filter(|section| section.header == header)
+            .flat_map(|section| section.values(key))
+            .next_back()
+            .cloned()
+            .unwrap_or_default()
+    };
+
     GitConfigData {
         name,
         email,
-        file_exists,
+        file_exists: true,
+        signing_key: get_value("user", "signingkey"),
+        gpg_sign: get_value("commit", "gpgsign"),
+        gpg_format: get_value("gpg", "format"),
+    }
+}
+
+/// A section of a gitconfig, such as `user` or `url "git@github.com:"`, with
+/// its entries in file order. Keys are lowercase, values are kept as written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitConfigSection {
+    pub header: String,
+    pub entries: Vec<(String, String)>,
+}
+
+impl GitConfigSection {
+    fn values(&self, key: &str) -> Vec<&String> {
+        self.entries
+            .iter()
+            .filter(|(entry_key, _)| entry_key == key)
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    fn keys(&self) -> Vec<&String> {
+        let mut keys: Vec<&String> = vec![];
+        for (key, _) in &self.entries {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        keys
+    }
+
+    /// Returns the dotted name git uses for `key`, e.g. `url.git@github.com:.insteadof`.
+    pub fn dotted_key(&self, key: &str) -> String {
+        match self.header.split_once(' ') {
+            Some((name, subsection)) => {
+                format!("{}.{}.{}", name, subsection.trim().trim_matches('"'), key)
+            }
+            None => format!("{}.{}", self.header, key),
+        }
+    }
+}
+
+fn normalize_header(header: &str) -> String {
+    let header = header.trim();
+
+    match header.split_once(|c: char| c.is_whitespace()) {
+        Some((name, subsection)) => format!("{} {}", name.to_lowercase(), subsection.trim()),
+        None => header.to_lowercase(),
+    }
+}
+
+pub fn parse_gitconfig(content: &str) -> Vec<GitConfigSection> {
+    let mut sections: Vec<GitConfigSection> = vec![];
+
+    for line in content.lines() {
+        let mut trimmed = line.trim();
+
+        if let Some(rest) = trimmed.strip_prefix('[')
+            && let Some((header, after)) = rest.split_once(']')
+        {
+            sections.push(GitConfigSection {
+                header: normalize_header(header),
+                entries: vec![],
+            });
+            trimmed = after.trim();
+        }
+
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+            continue;
+        }
+
+        let Some(section) = sections.last_mut() else {
+            continue;
+        };
+
+        let (key, value) = trimmed.split_once('=').unwrap_or((trimmed, "true"));
+        section
+            .entries
+            .push((key.trim().to_lowercase(), value.trim().to_string()));
     }
+
+    sections
+}
+
+pub fn render_gitconfig(sections: &[GitConfigSection]) -> String {
+    let mut content = String::new();
+
+    for section in sections {
+        content.push_str(&format!("[{}]\n", section.header));
+        for (key, value) in &section.entries {
+            content.push_str(&format!("\t{} = {}\n", key, value));
+        }
+    }
+
+    content
+}
+
+/// Applies `overlay` on top of `base`. Every key set in the overlay replaces
+/// all the values of that key in the same base section.
+pub fn merge_gitconfig(
+    base: &[GitConfigSection],
+    overlay: &[GitConfigSection],
+) -> Vec<GitConfigSection> {
+    let mut merged: Vec<GitConfigSection> = base.to_vec();
+
+    for section in overlay {
+        match merged
+            .iter_mut()
+            .find(|target| target.header == section.header)
+        {
+            Some(target) => {
+                let overlay_keys = section.keys();
+                target
+                    .entries
+                    .retain(|(key, _)| !overlay_keys.contains(&key));
+                target.entries.extend(section.entries.iter().cloned());
+            }
+            None => merged.push(section.clone()),
+        }
+    }
+
+    merged
+}
+
+/// Returns the keys of `current` whose values differ from `base`, so that
+/// merging the result over `base` gives `current` back.
+pub fn get_gitconfig_overlay(
+    base: &[GitConfigSection],
+    current: &[GitConfigSection],
+) -> Vec<GitConfigSection> {
+    let mut overlay: Vec<GitConfigSection> = vec![];
+
+    for section in current {
+        let base_section = base.iter().find(|target| target.header == section.header);
+
+        let entries: Vec<(String, String)> = section
+            .keys()
+            .into_iter()
+            .filter(|key| {
+                base_section
+                    .is_none_or(|base_section| base_section.values(key) != section.values(key))
+            })
+            .flat_map(|key| {
+                section
+                    .entries
+                    .iter()
+                    .filter(move |(entry_key, _)| entry_key == key)
+                    .cloned()
+            })
+            .collect();
+
+        if !entries.is_empty() {
+            match overlay
+                .iter_mut()
+                .find(|target| target.header == section.header)
+            {
+                Some(target) => target.entries.extend(entries),
+                None => overlay.push(GitConfigSection {
+                    header: section.header.clone(),
+                    entries,
+                }),
+            }
+        }
+    }
+
+    overlay
+}
+
+fn get_gitconfig_values(sections: &[GitConfigSection]) -> BTreeMap<(&str, &str), Vec<&str>> {
+    let mut values: BTreeMap<(&str, &str), Vec<&str>> = BTreeMap::new();
+
+    for section in sections {
+        for (key, value) in &section.entries {
+            values
+                .entry((section.header.as_str(), key.as_str()))
+                .or_default()
+                .push(value.as_str());
+        }
+    }
+
+    values
+}
+
+/// Returns whether two gitconfigs set the same values, in any order and
+/// regardless of comments and layout.
+pub fn is_same_gitconfig(a: &[GitConfigSection], b: &[GitConfigSection]) -> bool {
+    get_gitconfig_values(a) == get_gitconfig_values(b)
+}
+
+/// Returns whether `key` of `section` is set by the overlay rather than the base.
+pub fn is_overlay_key(overlay: &[GitConfigSection], header: &str, key: &str) -> bool {
+    overlay
+        .iter()
+        .any(|section| section.header == header && section.entries.iter().any(|(k, _)| k == key))
 }
@@ -0,0 +1,410 @@
+use std::fs;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::constants::HISTORY_MAX_ENTRIES;
+use crate::utils::{self, AppPaths};
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+const MONTH: u64 = 30 * DAY;
+
+/// A profile switch: the profile applied, in seconds since the Unix epoch.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub timestamp: u64,
+    pub profile_name: String,
+}
+
+/// A history lookup given instead of a profile name.
+#[derive(Debug)]
+pub enum HistoryRef {
+    /// `-N` or `@{N}`: the Nth most recently used profile, besides the
+    /// current one.
+    Steps(usize),
+    /// `@{yesterday}` and the like: the profile in use at this timestamp.
+    At(u64),
+}
+
+pub fn get_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Reads `~/.xks/.config/history`, one `<timestamp> <profile>` per line,
+/// oldest first.
+pub fn read_history(app_paths: &AppPaths) -> Vec<HistoryEntry> {
+    let Ok(content) = fs::read_to_string(&app_paths.history_file_path) else {
+        return vec![];
+    };
+
+    content
+        .lines()
+        .filter_map(|line| {
+            let (timestamp, profile_name) = line.trim().split_once(' ')?;
+
+            Some(HistoryEntry {
+                timestamp: timestamp.parse().ok()?,
+                profile_name: profile_name.trim().to_string(),
+            })
+        })
+        .filter(|entry| !entry.profile_name.is_empty())
+        .collect()
+}
+
+fn write_history(app_paths: &AppPaths, entries: &[HistoryEntry]) -> io::Result<()> {
+    let skipped = entries.len().saturating_sub(HISTORY_MAX_ENTRIES);
+    let content: String = entries[skipped..]
+        .iter()
+        .map(|entry| format!("{} {}\n", entry.timestamp, entry.profile_name))
+        .collect();
+
+    utils::write_to_file(app_paths.history_file_path.clone(), &content)
+}
+
+/// Records a switch to `profile_name`. The profile switched from is recorded
+/// first when the history does not end with it, so the first switch can be
+/// undone too.
+pub fn record_switch(
+    app_paths: &AppPaths,
+    previous_profile_name: Option<&str>,
+    profile_name: &str,
+) -> io::Result<()> {
+    let mut entries = read_history(app_paths);
+    let now = get_now();
+
+    if let Some(previous_profile_name) = previous_profile_name
+        && entries.last().map(|entry| entry.profile_name.as_str()) != Some(previous_profile_name)
+    {
+        entries.push(HistoryEntry {
+            timestamp: now,
+            profile_name: previous_profile_name.to_string(),
+        });
+    }
+
+    entries.push(HistoryEntry {
+        timestamp: now,
+        profile_name: profile_name.to_string(),
+    });
+
+    write_history(app_paths, &entries)
+}
+
+/// Renames the switches to a renamed profile.
+pub fn rename_profile(app_paths: &AppPaths, profile_name: &str, new_name: &str) {
+    let mut entries = read_history(app_paths);
+
+    if !entries
+        .iter()
+        .any(|entry| entry.profile_name == profile_name)
+    {
+        return;
+    }
+
+    for entry in entries.iter_mut() {
+        if entry.profile_name == profile_name {
+            entry.profile_name = new_name.to_string();
+        }
+    }
+
+    write_history(app_paths, &entries).ok();
+}
+
+/// Returns the profiles by most recent use, without repeats.
+pub fn get_recent_profiles(entries: &[HistoryEntry]) -> Vec<String> {
+    let mut profile_names: Vec<String> = vec![];
+
+    for entry in entries.iter().rev() {
+        if !profile_names.contains(&entry.profile_name) {
+            profile_names.push(entry.profile_name.clone());
+        }
+    }
+
+    profile_names
+}
+
+/// Returns the profile in use at `timestamp`, as far as the history knows.
+pub fn get_profile_at(entries: &[HistoryEntry], timestamp: u64) -> Option<String> {
+    entries
+        .iter()
+        .rev()
+        .find(|entry| entry.timestamp <= timestamp)
+        .map(|entry| entry.profile_name.clone())
+}
+
+fn parse_steps(text: &str) -> Option<usize> {
+    if text.is_empty() || !text.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    text.parse().ok().filter(|steps| *steps > 0)
+}
+
+/// Whether `arg` is `-N`, which goes back N profiles.
+pub fn is_history_step(arg: &str) -> bool {
+    arg.strip_prefix('-').and_then(parse_steps).is_some()
+}
+
+fn parse_unit(unit: &str) -> Option<u64> {
+    match unit.trim_end_matches('s') {
+        "" | "sec" | "second" => Some(1),
+        "m" | "min" | "minute" => Some(MINUTE),
+        "h" | "hour" => Some(HOUR),
+        "d" | "day" => Some(DAY),
+        "w" | "week" => Some(WEEK),
+        "month" => Some(MONTH),
+        _ => None,
+    }
+}
+
+/// Parses the seconds in `2.days.ago`, `2-days`, `2d` or `last-week`.
+fn parse_age(text: &str) -> Option<u64> {
+    let words: Vec<&str> = text
+        .split(['.', '-', ' ', '_'])
+        .filter(|word| !word.is_empty() && *word != "ago")
+        .collect();
+
+    match words.as_slice() {
+        ["yesterday"] => Some(DAY),
+        ["last", unit] => parse_unit(unit),
+        [count, unit] => count.parse::<u64>().ok()?.checked_mul(parse_unit(unit)?),
+        [compact] => {
+            let digits = compact.find(|c: char| !c.is_ascii_digit())?;
+            let (count, unit) = compact.split_at(digits);
+            count.parse::<u64>().ok()?.checked_mul(parse_unit(unit)?)
+        }
+        _ => None,
+    }
+}
+
+/// Parses `-N`, `@{N}` and `@{<time>}`, e.g. `@{yesterday}` or
+/// `@{2.days.ago}`. Returns `None` when `name` is not a history lookup.
+pub fn parse_history_ref(name: &str, now: u64) -> Option<Result<HistoryRef, String>> {
+    if let Some(steps) = name.strip_prefix('-').and_then(parse_steps) {
+        return Some(Ok(HistoryRef::Steps(steps)));
+    }
+
+    let lookup = name.strip_prefix("@{")?.strip_suffix('}')?;
+
+    if let Some(steps) = parse_steps(lookup) {
+        return Some(Ok(HistoryRef::Steps(steps)));
+    }
+
+    Some(
+        parse_age(&lookup.to_lowercase())
+            .map(|age| HistoryRef::At(now.saturating_sub(age)))
+            .ok_or_else(|| format!("Unrecognized history lookup {:?}.", name)),
+    )
+}
+
+/// Parses a `2024-05-01` date, at midnight UTC.
+fn parse_date(text: &str) -> Option<u64> {
+    let mut parts = text.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+
+    let is_leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if is_leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+
+    if !(1..=12).contains(&month) || !(1..=days_in_month).contains(&day) {
+        return None;
+    }
+
+    // days since 1970-01-01 from a civil date, the inverse of format_timestamp
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    u64::try_from(days).ok().map(|days| days * DAY)
+}
+
+/// Parses a point in time, as a date like `2024-05-01` or an age like
+/// `yesterday` or `2.days.ago`.
+pub fn parse_time(text: &str, now: u64) -> Option<u64> {
+    parse_date(text).or_else(|| parse_age(&text.to_lowercase()).map(|age| now.saturating_sub(age)))
+}
+
+/// Formats a timestamp as `2024-05-01 13:45 UTC`.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / DAY) as i64;
+    let seconds = timestamp % DAY;
+
+    // civil date from days since 1970-01-01, in the proleptic Gregorian calendar
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / HOUR,
+        seconds % HOUR / MINUTE
+    )
+}
+
+/// Formats the time since `timestamp` as `5 minutes ago`.
+pub fn format_age(timestamp: u64, now: u64) -> String {
+    let age = now.saturating_sub(timestamp);
+
+    let (count, unit) = match age {
+        0..MINUTE => return "just now".to_string(),
+        MINUTE..HOUR => (age / MINUTE, "minute"),
+        HOUR..DAY => (age / HOUR, "hour"),
+        DAY..WEEK => (age / DAY, "day"),
+        WEEK..MONTH => (age / WEEK, "week"),
+        _ => (age / MONTH, "month"),
+    };
+
+    format!(
+        "{} {}{} ago",
+        count,
+        unit,
+        if count == 1 { "" } else { "s" }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-05-10 12:00 UTC
+    const NOW: u64 = 1_715_342_400;
+
+    fn history_ref(name: &str) -> Option<Result<HistoryRef, String>> {
+        parse_history_ref(name, NOW)
+    }
+
+    #[test]
+    fn parses_history_steps() {
+        assert!(matches!(history_ref("-1"), Some(Ok(HistoryRef::Steps(1)))));
+        assert!(matches!(
+            history_ref("-12"),
+            Some(Ok(HistoryRef::Steps(12)))
+        ));
+        assert!(matches!(
+            history_ref("@{3}"),
+            Some(Ok(HistoryRef::Steps(3)))
+        ));
+
+        assert!(history_ref("-0").is_none());
+        assert!(history_ref("-x").is_none());
+        assert!(history_ref("work").is_none());
+        assert!(matches!(history_ref("@{0}"), Some(Err(_))));
+
+        assert!(is_history_step("-2"));
+        assert!(!is_history_step("-"));
+        assert!(!is_history_step("--json"));
+    }
+
+    #[test]
+    fn parses_history_times() {
+        let at = |name: &str| match history_ref(name) {
+            Some(Ok(HistoryRef::At(timestamp))) => Some(timestamp),
+            _ => None,
+        };
+
+        assert_eq!(at("@{yesterday}"), Some(NOW - DAY));
+        assert_eq!(at("@{Yesterday}"), Some(NOW - DAY));
+        assert_eq!(at("@{2.days.ago}"), Some(NOW - 2 * DAY));
+        assert_eq!(at("@{2-days}"), Some(NOW - 2 * DAY));
+        assert_eq!(at("@{2d}"), Some(NOW - 2 * DAY));
+        assert_eq!(at("@{3.hours.ago}"), Some(NOW - 3 * HOUR));
+        assert_eq!(at("@{last-week}"), Some(NOW - WEEK));
+        assert_eq!(at("@{1.month.ago}"), Some(NOW - MONTH));
+
+        assert!(matches!(history_ref("@{someday}"), Some(Err(_))));
+        assert!(matches!(history_ref("@{2.fortnights.ago}"), Some(Err(_))));
+    }
+
+    #[test]
+    fn rejects_ages_that_overflow() {
+        assert!(matches!(
+            history_ref("@{99999999999999999.weeks}"),
+            Some(Err(_))
+        ));
+        assert!(matches!(history_ref("@{99999999999999999w}"), Some(Err(_))));
+        assert_eq!(parse_time("99999999999999999w", NOW), None);
+        assert_eq!(parse_time("99999999999999999999d", NOW), None);
+
+        // the largest count that fits is still older than any history
+        assert_eq!(parse_time(&format!("{}s", u64::MAX), NOW), Some(0));
+    }
+
+    #[test]
+    fn parses_times() {
+        assert_eq!(parse_time("2d", NOW), Some(NOW - 2 * DAY));
+        assert_eq!(parse_time("last-week", NOW), Some(NOW - WEEK));
+        assert_eq!(parse_time("2024-05-10", NOW), Some(NOW - 12 * HOUR));
+        assert_eq!(parse_time("1970-01-01", NOW), Some(0));
+        assert_eq!(parse_time("2024-02-29", NOW), Some(1_709_164_800));
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        for text in [
+            "2024-13-01",
+            "2024-00-10",
+            "2024-05-00",
+            "2024-05-32",
+            "2024-04-31",
+            "2023-02-29",
+            "1900-02-29",
+            "1969-12-31",
+            "2024-05",
+            "2024-05-1x",
+        ] {
+            assert_eq!(parse_date(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn format_timestamp_inverts_parse_date() {
+        for date in [
+            "1970-01-01",
+            "1999-12-31",
+            "2000-02-29",
+            "2024-02-29",
+            "2024-03-01",
+            "2100-03-01",
+        ] {
+            let timestamp = parse_date(date).unwrap();
+            assert_eq!(format_timestamp(timestamp), format!("{} 00:00 UTC", date));
+        }
+
+        assert_eq!(format_timestamp(NOW + 45 * MINUTE), "2024-05-10 12:45 UTC");
+    }
+
+    #[test]
+    fn formats_ages() {
+        assert_eq!(format_age(NOW, NOW), "just now");
+        assert_eq!(format_age(NOW - MINUTE, NOW), "1 minute ago");
+        assert_eq!(format_age(NOW - 5 * HOUR, NOW), "5 hours ago");
+        assert_eq!(format_age(NOW - 2 * WEEK, NOW), "2 weeks ago");
+        assert_eq!(format_age(NOW + DAY, NOW), "just now");
+    }
+}
@@ -0,0 +1,80 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::process;
+
+use crate::constants::APP_NAME;
+use crate::utils::{self, AppPaths};
+
+/// An advisory lock on `~/.xks`, held by commands that change files and
+/// released when dropped.
+pub struct AppLock {
+    _file: File,
+}
+
+/// The outcome of taking the lock, when another xks holds it.
+pub enum LockError {
+    Busy(String),
+    Failed(String),
+}
+
+fn read_holder_pid(file: &mut File) -> String {
+    let mut pid = String::new();
+    file.seek(SeekFrom::Start(0)).ok();
+    file.read_to_string(&mut pid).ok();
+
+    match pid.trim() {
+        "" => "unknown".to_string(),
+        pid => pid.to_string(),
+    }
+}
+
+/// Takes the lock, or waits for it with `wait`. The lock file keeps the pid
+/// of its holder, so others can report it.
+pub fn acquire(app_paths: &AppPaths, wait: bool) -> Result<AppLock, LockError> {
+    let failed = |e: std::io::Error| {
+        LockError::Failed(format!(
+            "{}: Error: Could not lock file: {}\n\n{}",
+            APP_NAME,
+            app_paths.lock_file_path.display(),
+            e
+        ))
+    };
+
+    if let Some(parent) = app_paths.lock_file_path.parent() {
+        utils::create_private_dir_all(parent).map_err(failed)?;
+    }
+
+    // not truncated on open, the pid of the holder is still being read
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&app_paths.lock_file_path)
+        .map_err(failed)?;
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let pid = read_holder_pid(&mut file);
+
+            if !wait {
+                return Err(LockError::Busy(format!(
+                    "{}: Another xks is running (pid {}).\n\nTry again when it finishes, or run with --wait.",
+                    APP_NAME, pid
+                )));
+            }
+
+            eprintln!("Waiting for another xks (pid {}) to finish...", pid);
+            file.lock().map_err(failed)?;
+        }
+        Err(TryLockError::Error(e)) => return Err(failed(e)),
+    }
+
+    file.set_len(0).map_err(failed)?;
+    file.seek(SeekFrom::Start(0)).map_err(failed)?;
+    write!(file, "{}", process::id()).map_err(failed)?;
+    file.flush().map_err(failed)?;
+
+    Ok(AppLock { _file: file })
+}
@@ -1,86 +1,228 @@
 use std::env;
 use std::process;
 
+mod agent;
+mod aliases;
+mod archive;
+mod args;
+mod audit;
 mod cli;
+mod completion;
 mod constants;
+mod crypto;
+mod diff;
 mod git;
+mod history;
+mod json;
+mod keys;
+mod lock;
+mod manifest;
+mod picker;
+mod settings;
+mod ssh_config;
+mod store;
+mod trash;
+mod tui;
 mod utils;
 
-use constants::{APP_NAME, HELP_LINE, TOGGLE_PREV, YES_FLAG};
+use cli::CliError;
+use constants::EXIT_NOT_IN_USE;
+
+/// Reports a failed command and exits with its code, as a JSON result with
+/// `--json`.
+fn exit_with_error(json_command: &str, error: &CliError, json_flag: bool) -> ! {
+    if json_flag {
+        json::print_error(json_command, &error.message, error.code);
+    } else {
+        eprintln!("{}", error.message);
+    }
+    process::exit(error.code);
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let mut yes_flag: bool = false;
+    let args: Vec<String> = env::args().skip(1).collect();
 
-    let args: Vec<String> = args
-        .into_iter()
-        .filter(|arg| {
-            if arg == YES_FLAG {
-                yes_flag = true;
-            }
+    // hidden helper of the completion scripts, its words are not parsed
+    if args.first().is_some_and(|arg| arg == "__complete") {
+        cli::complete(&args[1..]);
+        return;
+    }
 
-            arg != YES_FLAG
-        })
-        .collect();
+    let parsed_args = match args::parse(&args) {
+        Ok(parsed_args) => parsed_args,
+        Err(error) => {
+            // the command is unknown here, so JSON output is guessed from the raw arguments
+            let json_flag = args.iter().any(|arg| arg == "--json");
+            exit_with_error("", &CliError::usage(error), json_flag);
+        }
+    };
 
-    let first_arg = args.get(1).map(|s| s.as_str()).unwrap_or_else(|| "_");
-    let second_arg = args.get(2).map(|s| s.as_str()).unwrap_or_else(|| "");
+    let yes_flag = parsed_args.flag(args::YES_OPTION);
+    let json_flag = parsed_args.flag(args::JSON_OPTION);
+    let dry_run = parsed_args.flag(args::DRY_RUN_OPTION);
+    let json_command = parsed_args.command.name;
+    let first_arg = parsed_args.positional(0);
+    let second_arg = parsed_args.positional(1);
 
-    if args.len() > 3 {
-        eprintln!(
-            "{}: Too many arguments provided.\n\n{}",
-            APP_NAME, HELP_LINE
-        );
-        process::exit(1);
+    if parsed_args.flag(args::HELP_OPTION) {
+        println!("\n{}\n", args::get_command_help(parsed_args.command));
+        return;
     }
 
-    match first_arg {
+    // held until the command returns, a dry run changes nothing
+    let _lock = if parsed_args.command.has_option(args::WAIT_OPTION) && !dry_run {
+        match cli::lock(parsed_args.flag(args::WAIT_OPTION)) {
+            Ok(lock) => Some(lock),
+            Err(e) => exit_with_error(json_command, &e, json_flag),
+        }
+    } else {
+        None
+    };
+
+    match parsed_args.command.name {
         "save" => {
-            if let Err(e) = cli::save(second_arg, yes_flag) {
-                eprintln!("{}", e);
-                process::exit(1);
+            if let Err(e) = cli::save(first_arg, yes_flag, json_flag, dry_run) {
+                exit_with_error(json_command, &e, json_flag);
             }
         }
-        "remove" | "delete" => {
-            if let Err(e) = cli::remove(second_arg, yes_flag) {
-                eprintln!("{}", e);
-                process::exit(1);
+        "remove" => {
+            if let Err(e) = cli::remove(first_arg, yes_flag, json_flag, dry_run) {
+                exit_with_error(json_command, &e, json_flag);
             }
         }
-        "use" => {
-            if let Err(e) = cli::use_profile(second_arg, yes_flag) {
-                eprintln!("{}", e);
-                process::exit(1);
+        "rename" => {
+            if let Err(e) = cli::rename(first_arg, second_arg, yes_flag) {
+                exit_with_error(json_command, &e, json_flag);
             }
         }
-        TOGGLE_PREV => {
-            if let Err(e) = cli::use_profile(TOGGLE_PREV, yes_flag) {
-                eprintln!("{}", e);
-                process::exit(1);
+        "copy" => {
+            if let Err(e) = cli::copy(first_arg, second_arg, yes_flag) {
+                exit_with_error(json_command, &e, json_flag);
             }
         }
+        "use" => {
+            if let Err(e) = cli::use_profile(first_arg, yes_flag, json_flag, dry_run) {
+                exit_with_error(json_command, &e, json_flag);
+            }
+        }
+        "status" => match cli::status(parsed_args.value(args::IS_OPTION)) {
+            Ok(true) => {}
+            Ok(false) => process::exit(EXIT_NOT_IN_USE),
+            Err(e) => exit_with_error(json_command, &e, json_flag),
+        },
         "discard" => {
-            if let Err(e) = cli::discard_files(yes_flag) {
-                eprintln!("{}", e);
-                process::exit(1);
+            if let Err(e) = cli::discard_files(yes_flag, json_flag, dry_run) {
+                exit_with_error(json_command, &e, json_flag);
+            }
+        }
+        "history" => {
+            if let Err(e) = cli::history(json_flag) {
+                exit_with_error(json_command, &e, json_flag);
+            }
+        }
+        "log" => {
+            if let Err(e) = cli::log(parsed_args.value(args::SINCE_OPTION), json_flag) {
+                exit_with_error(json_command, &e, json_flag);
+            }
+        }
+        "trash" => {
+            if let Err(e) = cli::trash(
+                first_arg,
+                second_arg,
+                parsed_args.value(args::OLDER_THAN_OPTION),
+                yes_flag,
+            ) {
+                exit_with_error(json_command, &e, json_flag);
+            }
+        }
+        "alias" => {
+            if let Err(e) = cli::alias(first_arg, second_arg, parsed_args.positional(2)) {
+                exit_with_error(json_command, &e, json_flag);
+            }
+        }
+        "export" => {
+            if let Err(e) = cli::export(
+                first_arg,
+                parsed_args.flag(args::ALL_OPTION),
+                parsed_args.value(args::OUTPUT_OPTION),
+                parsed_args.flag(args::ENCRYPT_OPTION),
+                yes_flag,
+            ) {
+                exit_with_error(json_command, &e, json_flag);
+            }
+        }
+        "import-archive" => {
+            if let Err(e) = cli::import_archive(
+                first_arg,
+                parsed_args.flag(args::RENAME_OPTION),
+                parsed_args.flag(args::OVERWRITE_OPTION),
+                parsed_args.flag(args::SKIP_OPTION),
+            ) {
+                exit_with_error(json_command, &e, json_flag);
             }
         }
-        "_" => {
-            // no command
-            if let Err(e) = cli::list() {
-                eprintln!("{}", e);
-                process::exit(1);
+        "show" => {
+            if let Err(e) = cli::show(first_arg, json_flag) {
+                exit_with_error(json_command, &e, json_flag);
             }
         }
-        "version" | "--version" | "-v" => {
-            cli::version();
+        "diff" => {
+            if let Err(e) = cli::diff(
+                first_arg,
+                second_arg,
+                parsed_args.flag(args::DECRYPT_OPTION),
+            ) {
+                exit_with_error(json_command, &e, json_flag);
+            }
+        }
+        "inherit" => {
+            if let Err(e) = cli::inherit(first_arg, second_arg) {
+                exit_with_error(json_command, &e, json_flag);
+            }
         }
-        "help" | "--help" | "-h" => {
-            cli::help();
+        "seal" => {
+            if let Err(e) = cli::seal(first_arg) {
+                exit_with_error(json_command, &e, json_flag);
+            }
+        }
+        "unseal" => {
+            if let Err(e) = cli::unseal(first_arg) {
+                exit_with_error(json_command, &e, json_flag);
+            }
+        }
+        "audit" => {
+            if let Err(e) = cli::audit() {
+                exit_with_error(json_command, &e, json_flag);
+            }
         }
-        _ => {
-            // wrong command
-            eprintln!("{}: Unrecognized command.\n\n{}", APP_NAME, HELP_LINE);
+        "repair" => {
+            if let Err(e) = cli::repair(yes_flag) {
+                exit_with_error(json_command, &e, json_flag);
+            }
+        }
+        "list" => {
+            if let Err(e) = cli::list(json_flag) {
+                exit_with_error(json_command, &e, json_flag);
+            }
+        }
+        "tui" => {
+            if let Err(e) = tui::run() {
+                exit_with_error(json_command, &e, json_flag);
+            }
+        }
+        "completions" => {
+            if let Err(e) = cli::completions(first_arg) {
+                exit_with_error(json_command, &e, json_flag);
+            }
+        }
+        "version" => {
+            cli::version(json_flag);
+        }
+        "help" => {
+            if let Err(e) = cli::help(first_arg) {
+                exit_with_error(json_command, &e, json_flag);
+            }
         }
+        _ => unreachable!("every command spec has a match arm"),
     }
 }
@@ -0,0 +1,76 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::constants::PROFILE_MANIFEST_FILE_NAME;
+use crate::utils;
+
+/// Metadata of a profile, stored as `key = value` lines in its `.manifest`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Manifest {
+    /// Profile whose `.gitconfig` this profile's `.gitconfig` is layered on.
+    pub base: Option<String>,
+}
+
+impl Manifest {
+    fn is_empty(&self) -> bool {
+        *self == Manifest::default()
+    }
+}
+
+pub fn read_manifest<T: AsRef<Path>>(profile_path: T) -> Manifest {
+    fs::read_to_string(profile_path.as_ref().join(PROFILE_MANIFEST_FILE_NAME))
+        .map(|content| parse_manifest(&content))
+        .unwrap_or_default()
+}
+
+pub fn parse_manifest(content: &str) -> Manifest {
+    let mut manifest = Manifest::default();
+
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+
+        let value = value.trim();
+
+        if key.trim() == "base" && !value.is_empty() {
+            manifest.base = Some(value.to_string());
+        }
+    }
+
+    manifest
+}
+
+pub fn write_manifest<T: AsRef<Path>>(profile_path: T, manifest: &Manifest) -> io::Result<()> {
+    let manifest_path = profile_path.as_ref().join(PROFILE_MANIFEST_FILE_NAME);
+
+    if manifest.is_empty() {
+        return match fs::remove_file(&manifest_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        };
+    }
+
+    let mut content = String::new();
+
+    if let Some(base) = &manifest.base {
+        content.push_str(&format!("base = {}\n", base));
+    }
+
+    utils::write_bytes_to_file(manifest_path, content.as_bytes())
+}
+
+/// Lists the profiles that inherit from `base_name`.
+pub fn get_layered_profiles<T: AsRef<Path>>(data_dir_path: T, base_name: &str) -> Vec<String> {
+    utils::get_profile_dirs(&data_dir_path)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|profile_dir| {
+            read_manifest(data_dir_path.as_ref().join(profile_dir))
+                .base
+                .as_deref()
+                == Some(base_name)
+        })
+        .collect()
+}
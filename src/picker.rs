@@ -0,0 +1,256 @@
+use std::collections::VecDeque;
+use std::io::{self, IsTerminal, Read, Write};
+use std::process::{Command, Stdio};
+
+const VISIBLE_ROWS: usize = 10;
+
+/// A row of the picker: the name that is returned, the details shown next to
+/// it, and extra text the filter matches besides the name.
+pub struct PickerItem {
+    pub name: String,
+    pub detail: String,
+    pub keywords: String,
+    pub active: bool,
+}
+
+/// Whether a picker can be shown: input and the picker output, on stderr,
+/// must both be a terminal.
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal() && io::stderr().is_terminal()
+}
+
+fn run_stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Puts the terminal in raw mode, and restores its settings when dropped.
+pub struct RawMode {
+    saved_settings: String,
+}
+
+impl RawMode {
+    pub fn enable() -> Result<RawMode, String> {
+        let saved_settings = run_stty(&["-g"])
+            .ok_or_else(|| "Error: Could not read terminal settings.".to_string())?;
+
+        run_stty(&["raw", "-echo"])
+            .ok_or_else(|| "Error: Could not set terminal to raw mode.".to_string())?;
+
+        Ok(RawMode { saved_settings })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        run_stty(&[&self.saved_settings]);
+    }
+}
+
+/// A key read in raw mode.
+pub enum Key {
+    Up,
+    Down,
+    Enter,
+    Backspace,
+    Escape,
+    Char(char),
+    Other,
+}
+
+/// Reads keys from stdin in raw mode. A single read can hold several keys,
+/// e.g. while an arrow key is held down, so the rest are kept for later.
+#[derive(Default)]
+pub struct KeyReader {
+    pending: VecDeque<Key>,
+}
+
+impl KeyReader {
+    pub fn read_key(&mut self) -> io::Result<Key> {
+        while self.pending.is_empty() {
+            let mut buffer = [0u8; 64];
+            let count = io::stdin().read(&mut buffer)?;
+
+            if count == 0 {
+                return Ok(Key::Escape);
+            }
+
+            self.pending.extend(parse_keys(&buffer[..count]));
+        }
+
+        Ok(self.pending.pop_front().unwrap_or(Key::Other))
+    }
+}
+
+fn parse_keys(mut bytes: &[u8]) -> Vec<Key> {
+    let mut keys: Vec<Key> = vec![];
+
+    while !bytes.is_empty() {
+        // escape sequences arrive whole, a lone escape is the key itself
+        let (key, length) = match bytes {
+            [27, b'[' | b'O', b'A', ..] => (Key::Up, 3),
+            [27, b'[' | b'O', b'B', ..] => (Key::Down, 3),
+            [27, b'[' | b'O', ..] => {
+                let length = bytes[2..]
+                    .iter()
+                    .position(|byte| byte.is_ascii_alphabetic() || *byte == b'~')
+                    .map_or(bytes.len(), |position| position + 3);
+                (Key::Other, length)
+            }
+            [3 | 4 | 27, ..] => (Key::Escape, 1),
+            [16, ..] => (Key::Up, 1),
+            [14, ..] => (Key::Down, 1),
+            [b'\r' | b'\n', ..] => (Key::Enter, 1),
+            [127 | 8, ..] => (Key::Backspace, 1),
+            _ => {
+                let length = match bytes[0] {
+                    0xf0.. => 4,
+                    0xe0.. => 3,
+                    0xc0.. => 2,
+                    _ => 1,
+                }
+                .min(bytes.len());
+
+                match std::str::from_utf8(&bytes[..length])
+                    .ok()
+                    .and_then(|text| text.chars().next())
+                {
+                    Some(c) if !c.is_control() => (Key::Char(c), length),
+                    _ => (Key::Other, length),
+                }
+            }
+        };
+
+        keys.push(key);
+        bytes = &bytes[length..];
+    }
+
+    keys
+}
+
+/// Whether the characters of `filter` appear in `text` in order, ignoring
+/// case.
+pub fn fuzzy_matches(text: &str, filter: &str) -> bool {
+    let mut text_chars = text.chars().flat_map(char::to_lowercase);
+
+    filter
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|filter_char| text_chars.any(|text_char| text_char == filter_char))
+}
+
+fn render(
+    title: &str,
+    filter: &str,
+    items: &[&PickerItem],
+    selected: usize,
+    name_width: usize,
+    drawn_lines: usize,
+) -> io::Result<usize> {
+    let mut stderr = io::stderr();
+
+    let mut lines = vec![title.to_string(), format!("> {}", filter)];
+
+    let first_row = selected.saturating_sub(VISIBLE_ROWS - 1);
+    for (index, item) in items.iter().enumerate().skip(first_row).take(VISIBLE_ROWS) {
+        let cursor = if index == selected { ">" } else { " " };
+        let active = if item.active { "*" } else { " " };
+        lines.push(format!(
+            "{}{} {:<width$}  {}",
+            cursor,
+            active,
+            item.name,
+            item.detail,
+            width = name_width
+        ));
+    }
+
+    if items.is_empty() {
+        lines.push("  (no matching profiles)".to_string());
+    }
+
+    if drawn_lines > 0 {
+        write!(stderr, "\x1b[{}A", drawn_lines)?;
+    }
+    // raw mode needs explicit carriage returns
+    write!(stderr, "\r\x1b[J{}\r\n", lines.join("\r\n"))?;
+    stderr.flush()?;
+
+    Ok(lines.len())
+}
+
+/// Shows `items` on stderr, filtered as the user types, and returns the name
+/// of the chosen item, or `None` when the picker is canceled.
+pub fn pick(title: &str, items: &[PickerItem]) -> Result<Option<String>, String> {
+    let raw_mode = RawMode::enable()?;
+
+    let name_width = items.iter().map(|item| item.name.len()).max().unwrap_or(0);
+    let mut filter = String::new();
+    let mut selected = items.iter().position(|item| item.active).unwrap_or(0);
+    let mut drawn_lines = 0;
+    let mut key_reader = KeyReader::default();
+
+    let picked = loop {
+        let visible_items: Vec<&PickerItem> = items
+            .iter()
+            .filter(|item| {
+                fuzzy_matches(&item.name, &filter) || fuzzy_matches(&item.keywords, &filter)
+            })
+            .collect();
+        selected = selected.min(visible_items.len().saturating_sub(1));
+
+        drawn_lines = render(
+            title,
+            &filter,
+            &visible_items,
+            selected,
+            name_width,
+            drawn_lines,
+        )
+        .map_err(|e| e.to_string())?;
+
+        match key_reader.read_key().map_err(|e| e.to_string())? {
+            Key::Up => selected = selected.saturating_sub(1),
+            Key::Down => selected += 1,
+            Key::Enter => {
+                if let Some(item) = visible_items.get(selected) {
+                    break Some(item.name.clone());
+                }
+            }
+            Key::Backspace => {
+                filter.pop();
+            }
+            Key::Escape => break None,
+            Key::Char(c) => {
+                filter.push(c);
+                selected = 0;
+            }
+            Key::Other => {}
+        }
+    };
+
+    // the picker is erased, so only the command output remains
+    let _ = write!(io::stderr(), "\x1b[{}A\r\x1b[J", drawn_lines);
+    drop(raw_mode);
+
+    Ok(picked)
+}
+
+/// Returns the rows and columns of the terminal, when they are known.
+pub fn get_terminal_size() -> Option<(usize, usize)> {
+    let size = run_stty(&["size"])?;
+    let (rows, columns) = size.split_once(' ')?;
+    let (rows, columns) = (rows.parse().ok()?, columns.parse().ok()?);
+
+    // some terminals report 0 0 when their size is unknown
+    (rows > 0 && columns > 0).then_some((rows, columns))
+}
@@ -0,0 +1,74 @@
+use std::fs;
+
+use crate::constants::{AUDIT_LOG_KEEP, AUDIT_LOG_MAX_SIZE};
+use crate::utils::AppPaths;
+
+/// Options read from `~/.xks/.config/settings`, one `key = value` per line.
+#[derive(Debug)]
+pub struct Settings {
+    /// Refuse to save or use profiles with DSA or short RSA keys.
+    pub refuse_weak_keys: bool,
+    /// Swap the profile keys loaded in ssh-agent on `use`.
+    pub ssh_agent: bool,
+    /// Seconds the keys added to ssh-agent stay loaded.
+    pub ssh_agent_lifetime: Option<u32>,
+    /// Log every `use`, `save`, `remove` and `discard`.
+    pub audit_log: bool,
+    /// Bytes the audit log grows to before it is rotated.
+    pub audit_log_max_size: u64,
+    /// Rotated audit logs kept, older ones are deleted.
+    pub audit_log_keep: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            refuse_weak_keys: false,
+            ssh_agent: false,
+            ssh_agent_lifetime: None,
+            audit_log: true,
+            audit_log_max_size: AUDIT_LOG_MAX_SIZE,
+            audit_log_keep: AUDIT_LOG_KEEP,
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> bool {
+    matches!(value.to_lowercase().as_str(), "true" | "yes" | "on" | "1")
+}
+
+pub fn get_settings(app_paths: &AppPaths) -> Settings {
+    let mut settings = Settings::default();
+
+    let Ok(content) = fs::read_to_string(&app_paths.settings_file_path) else {
+        return settings;
+    };
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, value)) = trimmed.split_once('=') else {
+            continue;
+        };
+
+        let value = value.trim();
+
+        match key.trim() {
+            "refuse_weak_keys" => settings.refuse_weak_keys = parse_bool(value),
+            "ssh_agent" => settings.ssh_agent = parse_bool(value),
+            "ssh_agent_lifetime" => settings.ssh_agent_lifetime = value.parse().ok(),
+            "audit_log" => settings.audit_log = parse_bool(value),
+            "audit_log_max_size" => {
+                settings.audit_log_max_size = value.parse().unwrap_or(AUDIT_LOG_MAX_SIZE)
+            }
+            "audit_log_keep" => settings.audit_log_keep = value.parse().unwrap_or(AUDIT_LOG_KEEP),
+            _ => {}
+        }
+    }
+
+    settings
+}
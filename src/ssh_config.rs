@@ -0,0 +1,332 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::constants::{SSH_CONFIG_FILE_NAME, SSH_DIR, TRACKED_FILE_NAMES};
+use crate::utils::{self, AppPaths};
+
+const ASSEMBLED_HEADER: &str = "# Assembled by xks from ~/.xks/.config/ssh_config and the profile config.\n# Everything above the unmanaged marker is replaced on `xks use`.\n";
+const PROFILE_MARKER: &str = "# --- xks: profile ---\n";
+const BASE_MARKER: &str = "# --- xks: base ---\n";
+const UNMANAGED_MARKER: &str = "# --- xks: unmanaged, local edits below are kept ---\n";
+// resets the scope left open by the last `Host` block of the previous section
+const SCOPE_RESET: &str = "Match all\n";
+
+/// A `Host` or `Match` block of an OpenSSH client config. Options before the
+/// first block are kept in a block without patterns.
+#[derive(Debug, Default, Clone)]
+pub struct HostBlock {
+    pub keyword: String,
+    pub patterns: Vec<String>,
+    pub host_name: Option<String>,
+    pub user: Option<String>,
+    pub identity_files: Vec<String>,
+}
+
+impl HostBlock {
+    pub fn label(&self) -> String {
+        if self.keyword.is_empty() {
+            "(global)".to_string()
+        } else if self.keyword == "host" {
+            self.patterns.join(" ")
+        } else {
+            format!("Match {}", self.patterns.join(" "))
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SshConfig {
+    pub blocks: Vec<HostBlock>,
+    pub includes: Vec<String>,
+}
+
+impl SshConfig {
+    pub fn identity_files(&self) -> Vec<&String> {
+        self.blocks
+            .iter()
+            .flat_map(|block| block.identity_files.iter())
+            .collect()
+    }
+}
+
+/// Splits a config line into arguments, honouring double quotes.
+fn split_arguments(text: &str) -> Vec<String> {
+    let mut arguments: Vec<String> = vec![];
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for c in text.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    arguments.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        arguments.push(current);
+    }
+
+    arguments
+}
+
+/// Splits `Keyword value` or `Keyword=value` into a lowercase keyword and
+/// its arguments.
+pub fn split_line(line: &str) -> Option<(String, Vec<String>)> {
+    let trimmed = line.trim();
+
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return None;
+    }
+
+    let keyword_end = trimmed
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(trimmed.len());
+    let keyword = trimmed[..keyword_end].to_lowercase();
+    let rest = trimmed[keyword_end..]
+        .trim_start()
+        .strip_prefix('=')
+        .unwrap_or(trimmed[keyword_end..].trim_start());
+
+    Some((keyword, split_arguments(rest)))
+}
+
+pub fn parse_ssh_config(content: &str) -> SshConfig {
+    let mut config = SshConfig::default();
+    let mut block = HostBlock::default();
+
+    for line in content.lines() {
+        let Some((keyword, arguments)) = split_line(line) else {
+            continue;
+        };
+
+        match keyword.as_str() {
+            "host" | "match" => {
+                let previous = std::mem::take(&mut block);
+
+                if !previous.keyword.is_empty() || !previous.identity_files.is_empty() {
+                    config.blocks.push(previous);
+                }
+
+                block.keyword = keyword;
+                block.patterns = arguments;
+            }
+            "hostname" => block.host_name = arguments.into_iter().next(),
+            "user" => block.user = arguments.into_iter().next(),
+            "identityfile" => block.identity_files.extend(arguments),
+            "include" => config.includes.extend(arguments),
+            _ => {}
+        }
+    }
+
+    if !block.keyword.is_empty() || !block.identity_files.is_empty() {
+        config.blocks.push(block);
+    }
+
+    config
+}
+
+/// Returns the tracked file name an `IdentityFile` points at, when it points
+/// inside `~/.ssh`.
+pub fn get_tracked_identity_name(identity_file: &str, ssh_dir_path: &Path) -> Option<String> {
+    if let Ok(filename) = Path::new(identity_file).strip_prefix(ssh_dir_path) {
+        let filename = filename.to_string_lossy();
+
+        return TRACKED_FILE_NAMES
+            .contains(&filename.as_ref())
+            .then(|| filename.into_owned());
+    }
+
+    let path = identity_file
+        .strip_prefix("~/")
+        .or_else(|| identity_file.strip_prefix("%d/"))
+        .or_else(|| identity_file.strip_prefix("$HOME/"))
+        .or_else(|| identity_file.strip_prefix("${HOME}/"))?;
+    let filename = path.strip_prefix(SSH_DIR)?.strip_prefix('/')?;
+
+    if TRACKED_FILE_NAMES.contains(&filename) {
+        Some(filename.to_string())
+    } else {
+        None
+    }
+}
+
+/// Lists the `IdentityFile` entries that point at keys missing from
+/// `profile_file_names`.
+pub fn get_identity_file_warnings(
+    config: &SshConfig,
+    profile_file_names: &[String],
+    ssh_dir_path: &Path,
+) -> Vec<String> {
+    let mut warnings: Vec<String> = vec![];
+
+    for identity_file in config.identity_files() {
+        match get_tracked_identity_name(identity_file, ssh_dir_path) {
+            Some(filename) if profile_file_names.contains(&filename) => {}
+            Some(_) => warnings.push(format!(
+                "IdentityFile {} is not in this profile.",
+                identity_file
+            )),
+            None => warnings.push(format!(
+                "IdentityFile {} is not managed by xks.",
+                identity_file
+            )),
+        }
+    }
+
+    warnings.dedup();
+    warnings
+}
+
+/// The sections of a `~/.ssh/config` assembled by xks that belong to the user:
+/// the profile fragment and the local unmanaged edits.
+#[derive(Debug, Default)]
+pub struct AssembledConfig {
+    pub fragment: String,
+    pub unmanaged: String,
+}
+
+pub fn split_assembled_config(content: &str) -> Option<AssembledConfig> {
+    let fragment_start = content.find(PROFILE_MARKER)? + PROFILE_MARKER.len();
+    let fragment_end = fragment_start + content[fragment_start..].find(BASE_MARKER)?;
+    let unmanaged_start = fragment_end + content[fragment_end..].find(UNMANAGED_MARKER)?;
+    let unmanaged = &content[unmanaged_start + UNMANAGED_MARKER.len()..];
+
+    // the newline written after the fragment is not part of it
+    let fragment = &content[fragment_start..fragment_end];
+
+    Some(AssembledConfig {
+        fragment: fragment.strip_suffix('\n').unwrap_or(fragment).to_string(),
+        unmanaged: unmanaged
+            .strip_prefix(SCOPE_RESET)
+            .unwrap_or(unmanaged)
+            .to_string(),
+    })
+}
+
+fn push_section(content: &mut String, section: &str) {
+    content.push_str(section);
+
+    if !section.is_empty() && !section.ends_with('\n') {
+        content.push('\n');
+    }
+}
+
+pub fn assemble_config(fragment: &str, base: &str, unmanaged: &str) -> String {
+    let mut content = String::from(ASSEMBLED_HEADER);
+
+    // the fragment is followed by one newline of its own, so it is read back
+    // byte for byte, whether or not it ends with a newline
+    content.push_str(PROFILE_MARKER);
+    content.push_str(fragment);
+    if !fragment.is_empty() {
+        content.push('\n');
+    }
+    content.push_str(BASE_MARKER);
+    content.push_str(SCOPE_RESET);
+    push_section(&mut content, base);
+    content.push_str(UNMANAGED_MARKER);
+    content.push_str(SCOPE_RESET);
+    push_section(&mut content, unmanaged);
+
+    content
+}
+
+/// Reads `~/.ssh/config`, returning only the profile fragment when the file
+/// was assembled with the base config.
+pub fn read_current_fragment(app_paths: &AppPaths) -> io::Result<String> {
+    let content = fs::read_to_string(app_paths.ssh_dir_path.join(SSH_CONFIG_FILE_NAME))?;
+
+    match split_assembled_config(&content) {
+        Some(assembled) => Ok(assembled.fragment),
+        None => Ok(content),
+    }
+}
+
+pub fn read_current_assembled(app_paths: &AppPaths) -> Option<AssembledConfig> {
+    let content = fs::read_to_string(app_paths.ssh_dir_path.join(SSH_CONFIG_FILE_NAME)).ok()?;
+    split_assembled_config(&content)
+}
+
+pub fn read_base_config(app_paths: &AppPaths) -> String {
+    fs::read_to_string(&app_paths.base_ssh_config_file_path).unwrap_or_default()
+}
+
+/// Writes `~/.ssh/config` from the profile fragment, the base config and the
+/// unmanaged edits. Without a base or unmanaged edits, the fragment is
+/// written as is, and no file is left when everything is empty.
+pub fn write_current_config(
+    app_paths: &AppPaths,
+    fragment: &str,
+    unmanaged: &str,
+) -> io::Result<()> {
+    let ssh_config_path = app_paths.ssh_dir_path.join(SSH_CONFIG_FILE_NAME);
+    let base = read_base_config(app_paths);
+
+    if base.trim().is_empty() && unmanaged.trim().is_empty() {
+        if fragment.is_empty() {
+            return match fs::remove_file(&ssh_config_path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+
+        return utils::write_tracked_file(&ssh_config_path, fragment.as_bytes());
+    }
+
+    let content = assemble_config(fragment, &base, unmanaged);
+    utils::write_tracked_file(&ssh_config_path, content.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assembled_fragment_round_trips() {
+        for fragment in ["", "\n", "Host work\n", "Host work", "Host work\n\n"] {
+            let content = assemble_config(fragment, "Host jump\n", "Host local\n");
+            let assembled = split_assembled_config(&content).unwrap();
+
+            assert_eq!(assembled.fragment, fragment);
+            assert_eq!(assembled.unmanaged, "Host local\n");
+        }
+    }
+
+    #[test]
+    fn assembled_unmanaged_edits_are_kept() {
+        for unmanaged in ["", "Host local\n", "Host local"] {
+            let content = assemble_config("Host work", "Host jump", unmanaged);
+            let assembled = split_assembled_config(&content).unwrap();
+            let expected = if unmanaged.is_empty() {
+                String::new()
+            } else {
+                format!("{}\n", unmanaged.trim_end())
+            };
+
+            assert_eq!(assembled.unmanaged, expected);
+            assert_eq!(
+                assemble_config("Host work", "Host jump", &assembled.unmanaged),
+                content
+            );
+        }
+    }
+
+    #[test]
+    fn assembled_config_keeps_sections_apart() {
+        let content = assemble_config("Host work", "Host jump", "");
+
+        assert!(content.starts_with(ASSEMBLED_HEADER));
+        assert!(content.contains(&format!(
+            "{}Host work\n{}{}Host jump\n",
+            PROFILE_MARKER, BASE_MARKER, SCOPE_RESET
+        )));
+        assert!(content.ends_with(&format!("{}{}", UNMANAGED_MARKER, SCOPE_RESET)));
+        assert!(split_assembled_config("Host work\n").is_none());
+    }
+}
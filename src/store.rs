@@ -0,0 +1,540 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+use crate::constants::{
+    APP_NAME, GITCONFIG_FILE_NAME, LAYERED_GITCONFIG_FILE_NAME, PRIVATE_KEY_MODE,
+    PROFILE_MANIFEST_FILE_NAME, SEALED_FILE_NAME, TRACKED_FILE_NAMES, WRONG_PASSPHRASE_ERR,
+};
+use crate::crypto::{self, MAX_PBKDF2_ITERATIONS, NONCE_LEN, PBKDF2_ITERATIONS, SALT_LEN};
+use crate::git;
+use crate::manifest;
+use crate::utils::{self, AppPaths, ProHash};
+
+const SEALED_FORMAT_LINE: &str = "xks-sealed-profile 2";
+const SEAL_KEY_LEN: usize = 32;
+
+/// A file of a profile, held in memory.
+#[derive(Debug, Clone)]
+pub struct FileEntry {
+    pub name: String,
+    pub content: Vec<u8>,
+}
+
+/// The cleartext header of a sealed profile. Instead of the hash of the
+/// files, which would let a copy of the file be checked against guesses, it
+/// holds a MAC of that hash under the seal key of this machine.
+#[derive(Debug)]
+pub struct SealedIndex {
+    pub tracked_file_names: Vec<String>,
+    mac: Vec<u8>,
+    iterations: u32,
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+}
+
+impl SealedIndex {
+    /// Whether `hash` is the hash of the sealed files. Always false when the
+    /// profile was sealed on another machine.
+    pub fn has_hash(&self, seal_key: &[u8], hash: &str) -> bool {
+        crypto::verify_hmac_sha256(seal_key, hash.as_bytes(), &self.mac)
+    }
+}
+
+/// Reads the random key of this machine that sealed profiles MAC the hash of
+/// their files with.
+pub fn read_seal_key(app_paths: &AppPaths) -> Option<Vec<u8>> {
+    fs::read(&app_paths.seal_key_file_path)
+        .ok()
+        .filter(|seal_key| seal_key.len() == SEAL_KEY_LEN)
+}
+
+/// Reads the seal key of this machine, creating it the first time.
+pub fn get_or_create_seal_key(app_paths: &AppPaths) -> Result<Vec<u8>, String> {
+    if let Some(seal_key) = read_seal_key(app_paths) {
+        return Ok(seal_key);
+    }
+
+    let seal_key = crypto::random_bytes(SEAL_KEY_LEN)
+        .map_err(|_| format!("{}: Error: Could not read random bytes.", APP_NAME))?;
+
+    utils::write_file_with_mode(&app_paths.seal_key_file_path, &seal_key, PRIVATE_KEY_MODE)
+        .map_err(|_| {
+            format!(
+                "{}: Error: Could not write file: {}",
+                APP_NAME,
+                app_paths.seal_key_file_path.display()
+            )
+        })?;
+
+    Ok(seal_key)
+}
+
+pub fn is_sealed<T: AsRef<Path>>(profile_path: T) -> bool {
+    profile_path.as_ref().join(SEALED_FILE_NAME).is_file()
+}
+
+/// Hashes the entries the same way `utils::get_files_hash` hashes files.
+pub fn get_entries_hash(entries: &[FileEntry]) -> String {
+    let mut hasher = Sha256::new();
+    for entry in entries {
+        hasher.update(&entry.content);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+pub fn encode_entries(entries: &[FileEntry]) -> Vec<u8> {
+    let mut data: Vec<u8> = vec![];
+
+    for entry in entries {
+        data.extend_from_slice(&(entry.name.len() as u32).to_be_bytes());
+        data.extend_from_slice(entry.name.as_bytes());
+        data.extend_from_slice(&(entry.content.len() as u64).to_be_bytes());
+        data.extend_from_slice(&entry.content);
+    }
+
+    data
+}
+
+pub fn decode_entries(mut data: &[u8]) -> Option<Vec<FileEntry>> {
+    let mut entries: Vec<FileEntry> = vec![];
+
+    while !data.is_empty() {
+        let name_len = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+        data = &data[4..];
+        let name = String::from_utf8(data.get(..name_len)?.to_vec()).ok()?;
+        data = &data[name_len..];
+
+        let content_len = u64::from_be_bytes(data.get(..8)?.try_into().ok()?) as usize;
+        data = &data[8..];
+        let content = data.get(..content_len)?.to_vec();
+        data = &data[content_len..];
+
+        entries.push(FileEntry { name, content });
+    }
+
+    Some(entries)
+}
+
+fn split_sealed_file(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let header_end = data.windows(2).position(|w| w == b"\n\n")? + 2;
+    Some(data.split_at(header_end))
+}
+
+fn parse_sealed_header(header: &[u8]) -> Option<SealedIndex> {
+    let header = std::str::from_utf8(header).ok()?;
+    let mut lines = header.lines();
+
+    if lines.next()? != SEALED_FORMAT_LINE {
+        return None;
+    }
+
+    let mut mac: Vec<u8> = vec![];
+    let mut tracked_file_names: Vec<String> = vec![];
+    let mut iterations: u32 = 0;
+    let mut salt: Vec<u8> = vec![];
+    let mut nonce: Vec<u8> = vec![];
+
+    for line in lines {
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+
+        match key {
+            "mac" => mac = crypto::from_hex(value)?,
+            "files" => {
+                tracked_file_names = value.split_whitespace().map(String::from).collect();
+            }
+            "iterations" => iterations = value.parse().ok()?,
+            "salt" => salt = crypto::from_hex(value)?,
+            "nonce" => nonce = crypto::from_hex(value)?,
+            _ => {}
+        }
+    }
+
+    if mac.is_empty()
+        || !(1..=MAX_PBKDF2_ITERATIONS).contains(&iterations)
+        || salt.len() != SALT_LEN
+        || nonce.len() != NONCE_LEN
+    {
+        return None;
+    }
+
+    Some(SealedIndex {
+        mac,
+        tracked_file_names,
+        iterations,
+        salt,
+        nonce,
+    })
+}
+
+fn corrupted_msg<T: AsRef<Path>>(profile_path: T) -> String {
+    format!(
+        "{}: Error: Sealed profile is corrupted: {}",
+        APP_NAME,
+        profile_path.as_ref().display()
+    )
+}
+
+pub fn read_sealed_index<T: AsRef<Path>>(profile_path: T) -> Result<SealedIndex, String> {
+    let data = fs::read(profile_path.as_ref().join(SEALED_FILE_NAME))
+        .map_err(|_| corrupted_msg(&profile_path))?;
+
+    split_sealed_file(&data)
+        .and_then(|(header, _)| parse_sealed_header(header))
+        .ok_or_else(|| corrupted_msg(&profile_path))
+}
+
+/// Decrypts a sealed profile and checks its integrity.
+pub fn open_sealed<T: AsRef<Path>>(
+    profile_path: T,
+    passphrase: &str,
+) -> Result<Vec<FileEntry>, String> {
+    let data = fs::read(profile_path.as_ref().join(SEALED_FILE_NAME))
+        .map_err(|_| corrupted_msg(&profile_path))?;
+
+    let (header, body) = split_sealed_file(&data).ok_or_else(|| corrupted_msg(&profile_path))?;
+    let index = parse_sealed_header(header).ok_or_else(|| corrupted_msg(&profile_path))?;
+
+    let key = crypto::derive_key(passphrase, &index.salt, index.iterations);
+    let plaintext = crypto::open(&key, &index.nonce, header, body)
+        .ok_or_else(|| format!("{}: {}", APP_NAME, WRONG_PASSPHRASE_ERR))?;
+
+    decode_entries(&plaintext).ok_or_else(|| corrupted_msg(&profile_path))
+}
+
+/// Encrypts `entries` into the sealed file of `profile_path`. The file names
+/// and the MAC of the hash stay readable, so listings work without a
+/// passphrase. The whole header is authenticated with the files.
+pub fn write_sealed<T: AsRef<Path>>(
+    profile_path: T,
+    entries: &[FileEntry],
+    passphrase: &str,
+    seal_key: &[u8],
+) -> Result<(), String> {
+    let mac = crypto::hmac_sha256(seal_key, get_entries_hash(entries).as_bytes());
+
+    let random_err = |_| format!("{}: Error: Could not read random bytes.", APP_NAME);
+    let salt = crypto::random_bytes(SALT_LEN).map_err(random_err)?;
+    let nonce = crypto::random_bytes(NONCE_LEN).map_err(random_err)?;
+
+    let file_names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+    let header = format!(
+        "{}\nfiles {}\nmac {}\niterations {}\nsalt {}\nnonce {}\n\n",
+        SEALED_FORMAT_LINE,
+        file_names.join(" "),
+        crypto::to_hex(&mac),
+        PBKDF2_ITERATIONS,
+        crypto::to_hex(&salt),
+        crypto::to_hex(&nonce)
+    );
+
+    let key = crypto::derive_key(passphrase, &salt, PBKDF2_ITERATIONS);
+    let ciphertext = crypto::seal(&key, &nonce, header.as_bytes(), &encode_entries(entries));
+
+    let mut data = header.into_bytes();
+    data.extend_from_slice(&ciphertext);
+
+    let sealed_file_path = profile_path.as_ref().join(SEALED_FILE_NAME);
+
+    utils::write_file_with_mode(&sealed_file_path, &data, PRIVATE_KEY_MODE).map_err(|_| {
+        format!(
+            "{}: Error: Could not write file: {}",
+            APP_NAME,
+            sealed_file_path.display()
+        )
+    })
+}
+
+/// Reads the current files listed in `currfiles_prohash`.
+pub fn read_current_entries(
+    app_paths: &AppPaths,
+    currfiles_prohash: &ProHash,
+) -> Result<Vec<FileEntry>, String> {
+    let mut entries: Vec<FileEntry> = vec![];
+
+    for filename in &currfiles_prohash.tracked_file_names {
+        let content = utils::read_current_file(app_paths, filename)
+            .map_err(|_| format!("{}: Error: Could not read file: {}", APP_NAME, filename))?;
+
+        entries.push(FileEntry {
+            name: filename.clone(),
+            content,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Reads the tracked files of a plain profile, sorted by name.
+pub fn read_plain_entries<T: AsRef<Path>>(profile_path: T) -> Result<Vec<FileEntry>, String> {
+    let mut entries: Vec<FileEntry> = vec![];
+
+    for filename in utils::get_files(&profile_path)? {
+        if !TRACKED_FILE_NAMES.contains(&filename.as_str()) {
+            continue;
+        }
+
+        let content = fs::read(profile_path.as_ref().join(&filename))
+            .map_err(|_| format!("{}: Error: Could not read file: {}", APP_NAME, filename))?;
+
+        entries.push(FileEntry {
+            name: filename,
+            content,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Reads the `.gitconfig` of the base profile a layered profile inherits from.
+pub fn read_base_gitconfig(profile_path: &Path) -> Result<Option<String>, String> {
+    let Some(base_name) = manifest::read_manifest(profile_path).base else {
+        return Ok(None);
+    };
+
+    if !utils::is_safe_profile_name(&base_name) {
+        return Err(format!(
+            "{}: Error: Invalid base profile name in {}: {:?}",
+            APP_NAME,
+            profile_path.join(PROFILE_MANIFEST_FILE_NAME).display(),
+            base_name
+        ));
+    }
+
+    let base_path = profile_path.with_file_name(&base_name);
+
+    if !base_path.is_dir() {
+        return Err(format!(
+            "{}: Base profile {:?} not found.",
+            APP_NAME, base_name
+        ));
+    }
+
+    if is_sealed(&base_path) {
+        return Err(format!(
+            "{}: Base profile {:?} is sealed, base profiles must be plain.",
+            APP_NAME, base_name
+        ));
+    }
+
+    match fs::read_to_string(base_path.join(GITCONFIG_FILE_NAME)) {
+        Ok(content) => Ok(Some(content)),
+        Err(_) => Ok(Some(String::new())),
+    }
+}
+
+/// Replaces the `.gitconfig` of a layered profile with its base merged in.
+/// The full `.gitconfig` saved with the profile is used as long as it still
+/// matches the merge, so its comments and layout are kept.
+fn apply_base_gitconfig(profile_path: &Path, entries: &mut Vec<FileEntry>) -> Result<(), String> {
+    let Some(base_content) = read_base_gitconfig(profile_path)? else {
+        return Ok(());
+    };
+
+    let overlay_content = entries
+        .iter()
+        .find(|entry| entry.name == GITCONFIG_FILE_NAME)
+        .map(|entry| String::from_utf8_lossy(&entry.content).into_owned())
+        .unwrap_or_default();
+
+    let merged = git::merge_gitconfig(
+        &git::parse_gitconfig(&base_content),
+        &git::parse_gitconfig(&overlay_content),
+    );
+
+    let full_content = fs::read_to_string(profile_path.join(LAYERED_GITCONFIG_FILE_NAME))
+        .ok()
+        .filter(|full_content| {
+            git::is_same_gitconfig(&git::parse_gitconfig(full_content), &merged)
+        });
+
+    entries.retain(|entry| entry.name != GITCONFIG_FILE_NAME);
+
+    let content = match full_content {
+        Some(full_content) => full_content,
+        None if merged.is_empty() => return Ok(()),
+        None => git::render_gitconfig(&merged),
+    };
+
+    entries.push(FileEntry {
+        name: GITCONFIG_FILE_NAME.to_string(),
+        content: content.into_bytes(),
+    });
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(())
+}
+
+/// Reads the tracked files of a profile as they are applied by `use`, asking
+/// for the passphrase when the profile is sealed, and merging the base
+/// `.gitconfig` into layered profiles. A profile sealed on another machine is
+/// sealed again with the seal key of this one, so it can be shown as in use.
+pub fn read_profile_entries(
+    app_paths: &AppPaths,
+    profile_path: &Path,
+    profile_name: &str,
+) -> Result<Vec<FileEntry>, String> {
+    if is_sealed(profile_path) {
+        let prompt = format!("Passphrase for profile {:?}: ", profile_name);
+        let passphrase = utils::read_passphrase(&prompt)?;
+        let entries = open_sealed(profile_path, &passphrase)?;
+
+        let index = read_sealed_index(profile_path)?;
+        let seal_key = get_or_create_seal_key(app_paths)?;
+
+        if !index.has_hash(&seal_key, &get_entries_hash(&entries)) {
+            write_sealed(profile_path, &entries, &passphrase, &seal_key).ok();
+        }
+
+        return Ok(entries);
+    }
+
+    let mut entries = read_plain_entries(profile_path)?;
+    apply_base_gitconfig(profile_path, &mut entries)?;
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::GITCONFIG_FILE_NAME;
+    use std::path::PathBuf;
+
+    /// A profile directory of its own for every test, removed when dropped.
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> TestDir {
+            let path = std::env::temp_dir().join(format!("xks-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TestDir(path)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    const SEAL_KEY: &[u8] = &[0; SEAL_KEY_LEN];
+
+    fn entries() -> Vec<FileEntry> {
+        vec![
+            FileEntry {
+                name: GITCONFIG_FILE_NAME.to_string(),
+                content: b"[user]\n\tname = Alex\n".to_vec(),
+            },
+            FileEntry {
+                name: "id_ed25519".to_string(),
+                content: b"private key".to_vec(),
+            },
+        ]
+    }
+
+    fn names_and_contents(entries: &[FileEntry]) -> Vec<(String, Vec<u8>)> {
+        entries
+            .iter()
+            .map(|entry| (entry.name.clone(), entry.content.clone()))
+            .collect()
+    }
+
+    /// Flips one byte of the sealed file, at `offset` from the start of the
+    /// header or of the body.
+    fn tamper(dir: &TestDir, in_header: bool, offset: usize) {
+        let sealed_file_path = dir.0.join(SEALED_FILE_NAME);
+        let mut data = fs::read(&sealed_file_path).unwrap();
+        let (header, _) = split_sealed_file(&data).unwrap();
+        let position = if in_header {
+            offset
+        } else {
+            header.len() + offset
+        };
+
+        data[position] ^= 0x01;
+        fs::write(&sealed_file_path, data).unwrap();
+    }
+
+    #[test]
+    fn sealed_profile_round_trips() {
+        let dir = TestDir::new("seal-round-trip");
+        write_sealed(&dir.0, &entries(), "correct horse", SEAL_KEY).unwrap();
+
+        let index = read_sealed_index(&dir.0).unwrap();
+        assert_eq!(
+            index.tracked_file_names,
+            [GITCONFIG_FILE_NAME, "id_ed25519"]
+        );
+
+        let opened = open_sealed(&dir.0, "correct horse").unwrap();
+        assert_eq!(names_and_contents(&opened), names_and_contents(&entries()));
+    }
+
+    #[test]
+    fn sealed_header_only_matches_the_hash_under_the_seal_key() {
+        let dir = TestDir::new("seal-header-mac");
+        write_sealed(&dir.0, &entries(), "correct horse", SEAL_KEY).unwrap();
+
+        let hash = get_entries_hash(&entries());
+        let data = fs::read(dir.0.join(SEALED_FILE_NAME)).unwrap();
+        assert!(!String::from_utf8_lossy(&data).contains(&hash));
+
+        let index = read_sealed_index(&dir.0).unwrap();
+        assert!(index.has_hash(SEAL_KEY, &hash));
+        assert!(!index.has_hash(&[1; SEAL_KEY_LEN], &hash));
+        assert!(!index.has_hash(SEAL_KEY, &get_entries_hash(&entries()[1..])));
+    }
+
+    #[test]
+    fn sealed_profile_rejects_wrong_passphrase() {
+        let dir = TestDir::new("seal-wrong-passphrase");
+        write_sealed(&dir.0, &entries(), "correct horse", SEAL_KEY).unwrap();
+
+        let err = open_sealed(&dir.0, "battery staple").unwrap_err();
+        assert!(err.contains(WRONG_PASSPHRASE_ERR));
+    }
+
+    #[test]
+    fn sealed_profile_rejects_tampered_header() {
+        let dir = TestDir::new("seal-tampered-header");
+        write_sealed(&dir.0, &entries(), "correct horse", SEAL_KEY).unwrap();
+
+        // a character of the first file name, on the line after the format line
+        tamper(&dir, true, SEALED_FORMAT_LINE.len() + "\nfiles ".len() + 1);
+
+        let err = open_sealed(&dir.0, "correct horse").unwrap_err();
+        assert!(err.contains(WRONG_PASSPHRASE_ERR));
+    }
+
+    #[test]
+    fn sealed_profile_rejects_tampered_body() {
+        let dir = TestDir::new("seal-tampered-body");
+        write_sealed(&dir.0, &entries(), "correct horse", SEAL_KEY).unwrap();
+
+        tamper(&dir, false, 3);
+
+        let err = open_sealed(&dir.0, "correct horse").unwrap_err();
+        assert!(err.contains(WRONG_PASSPHRASE_ERR));
+    }
+
+    #[test]
+    fn sealed_header_rejects_too_many_iterations() {
+        let dir = TestDir::new("seal-iterations");
+        write_sealed(&dir.0, &entries(), "correct horse", SEAL_KEY).unwrap();
+
+        let sealed_file_path = dir.0.join(SEALED_FILE_NAME);
+        let data = fs::read(&sealed_file_path).unwrap();
+        let (header, body) = split_sealed_file(&data).unwrap();
+        let header = String::from_utf8(header.to_vec()).unwrap().replace(
+            &format!("iterations {}", PBKDF2_ITERATIONS),
+            &format!("iterations {}", MAX_PBKDF2_ITERATIONS + 1),
+        );
+        fs::write(&sealed_file_path, [header.as_bytes(), body].concat()).unwrap();
+
+        assert!(read_sealed_index(&dir.0).is_err());
+        let err = open_sealed(&dir.0, "correct horse").unwrap_err();
+        assert!(err.contains("corrupted"));
+    }
+}
@@ -0,0 +1,88 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::history;
+use crate::utils::{self, AppPaths};
+
+/// A removed profile, kept in `~/.xks/.config/trash/<timestamp>-<profile>`.
+#[derive(Debug)]
+pub struct TrashEntry {
+    pub profile_name: String,
+    /// When the profile was removed, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub path: PathBuf,
+}
+
+impl TrashEntry {
+    /// The name of the entry in the trash, unique unlike the profile name.
+    pub fn get_trash_name(&self) -> String {
+        format!("{}-{}", self.timestamp, self.profile_name)
+    }
+}
+
+/// Reads the removed profiles, most recently removed first.
+pub fn read_trash(app_paths: &AppPaths) -> Vec<TrashEntry> {
+    let Ok(dir_entries) = fs::read_dir(&app_paths.trash_dir_path) else {
+        return vec![];
+    };
+
+    let mut entries: Vec<TrashEntry> = dir_entries
+        .filter_map(Result::ok)
+        .filter(|dir_entry| dir_entry.path().is_dir())
+        .filter_map(|dir_entry| {
+            let trash_name = dir_entry.file_name().to_string_lossy().into_owned();
+            let (timestamp, profile_name) = trash_name.split_once('-')?;
+
+            Some(TrashEntry {
+                profile_name: profile_name.to_string(),
+                timestamp: timestamp.parse().ok()?,
+                path: dir_entry.path(),
+            })
+        })
+        .filter(|entry| !entry.profile_name.is_empty())
+        .collect();
+
+    entries.sort_by(|a, b| {
+        b.timestamp
+            .cmp(&a.timestamp)
+            .then_with(|| a.profile_name.cmp(&b.profile_name))
+    });
+
+    entries
+}
+
+/// Moves a saved profile to the trash.
+pub fn move_to_trash(app_paths: &AppPaths, profile_name: &str) -> io::Result<TrashEntry> {
+    utils::create_private_dir_all(&app_paths.trash_dir_path)?;
+
+    // a profile removed twice within a second gets the next free second
+    let mut entry = TrashEntry {
+        profile_name: profile_name.to_string(),
+        timestamp: history::get_now(),
+        path: PathBuf::new(),
+    };
+    while app_paths
+        .trash_dir_path
+        .join(entry.get_trash_name())
+        .exists()
+    {
+        entry.timestamp += 1;
+    }
+
+    entry.path = app_paths.trash_dir_path.join(entry.get_trash_name());
+    fs::rename(app_paths.data_dir_path.join(profile_name), &entry.path)?;
+
+    Ok(entry)
+}
+
+/// Finds a removed profile by its trash name, or by its profile name, in
+/// which case the most recently removed one is returned.
+pub fn find_entry(entries: Vec<TrashEntry>, name: &str) -> Option<TrashEntry> {
+    let position = entries
+        .iter()
+        .position(|entry| entry.get_trash_name() == name)
+        .or_else(|| entries.iter().position(|entry| entry.profile_name == name))?;
+
+    entries.into_iter().nth(position)
+}
@@ -0,0 +1,262 @@
+use std::io::{self, Write};
+
+use crate::cli::{self, CliError, ProfileSummary};
+use crate::constants::APP_NAME;
+use crate::picker::{self, Key, KeyReader, RawMode};
+
+const FOOTER: &str = "Enter/u use  s save  r rename  x remove  d diff  q quit";
+
+/// An action of the dashboard, run with the terminal back in normal mode so
+/// the cli functions can print and prompt as usual.
+enum Action {
+    Use,
+    Save,
+    Rename,
+    Remove,
+    Diff,
+}
+
+/// Full-screen mode: the alternate screen in raw mode, left when dropped.
+struct Screen {
+    _raw_mode: RawMode,
+}
+
+impl Screen {
+    fn enter() -> Result<Screen, String> {
+        let raw_mode = RawMode::enable()?;
+
+        let mut stderr = io::stderr();
+        write!(stderr, "\x1b[?1049h\x1b[?25l").map_err(|e| e.to_string())?;
+        stderr.flush().map_err(|e| e.to_string())?;
+
+        Ok(Screen {
+            _raw_mode: raw_mode,
+        })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let mut stderr = io::stderr();
+        let _ = write!(stderr, "\x1b[?25h\x1b[?1049l");
+        let _ = stderr.flush();
+    }
+}
+
+fn get_detail_lines(summary: &ProfileSummary) -> Vec<String> {
+    let mut lines = vec![format!(
+        "[profile: {}] {}",
+        summary.name,
+        if summary.active {
+            "in use"
+        } else {
+            "not in use"
+        }
+    )];
+
+    if summary.sealed {
+        lines.push("  sealed, identity and keys are shown once unsealed".to_string());
+        return lines;
+    }
+
+    lines.push(format!("  name:  {:?}", summary.git_name));
+    lines.push(format!("  email: {:?}", summary.email));
+
+    if summary.keys.is_empty() {
+        lines.push("  ssh keys: none".to_string());
+    } else {
+        lines.push(format!("  ssh keys ({}):", summary.keys.len()));
+        for (key_name, fingerprint) in &summary.keys {
+            lines.push(format!("    {:<12}{}", key_name, fingerprint));
+        }
+    }
+
+    lines
+}
+
+fn draw(summaries: &[ProfileSummary], selected: usize, message: &str) -> io::Result<()> {
+    let (rows, columns) = picker::get_terminal_size().unwrap_or((24, 80));
+
+    let current_names: Vec<&str> = summaries
+        .iter()
+        .filter(|summary| summary.active)
+        .map(|summary| summary.name.as_str())
+        .collect();
+    let current = if current_names.is_empty() {
+        "none".to_string()
+    } else {
+        current_names.join(" - ")
+    };
+
+    let mut lines = vec![
+        format!(
+            "{} - saved profiles: {}, current: {}",
+            APP_NAME,
+            summaries.len(),
+            current
+        ),
+        String::new(),
+    ];
+
+    let detail_lines = summaries
+        .get(selected)
+        .map(get_detail_lines)
+        .unwrap_or_default();
+
+    // the list gets the rows left by the header, the details and the footer
+    let list_rows = rows
+        .saturating_sub(lines.len() + detail_lines.len() + 4)
+        .max(1);
+    let first_row = selected.saturating_sub(list_rows - 1);
+    let name_width = summaries
+        .iter()
+        .map(|summary| summary.name.len())
+        .max()
+        .unwrap_or(0);
+
+    if summaries.is_empty() {
+        lines.push(format!(
+            "  No saved profiles, save one with: {} save <profile>",
+            APP_NAME
+        ));
+    }
+
+    for (index, summary) in summaries.iter().enumerate().skip(first_row).take(list_rows) {
+        let cursor = if index == selected { ">" } else { " " };
+        let active = if summary.active { "*" } else { " " };
+        let detail = if summary.sealed {
+            "(sealed)"
+        } else if summary.email.is_empty() {
+            "(no email)"
+        } else {
+            summary.email.as_str()
+        };
+
+        lines.push(format!(
+            "{}{} {:<width$}  {}",
+            cursor,
+            active,
+            summary.name,
+            detail,
+            width = name_width
+        ));
+    }
+
+    lines.push(String::new());
+    lines.extend(detail_lines);
+    lines.push(String::new());
+    lines.push(if message.is_empty() {
+        FOOTER.to_string()
+    } else {
+        message.to_string()
+    });
+
+    let lines: Vec<String> = lines
+        .into_iter()
+        .map(|line| line.chars().take(columns).collect())
+        .collect();
+
+    let mut stderr = io::stderr();
+    write!(stderr, "\x1b[H\x1b[J{}", lines.join("\r\n"))?;
+    stderr.flush()
+}
+
+fn read_line(prompt: &str) -> Result<String, String> {
+    eprint!("{}", prompt);
+    io::stderr().flush().map_err(|e| e.to_string())?;
+
+    let mut input = String::new();
+    io::stdin()
+        .read_line(&mut input)
+        .map_err(|_| "Error: Reading input.".to_string())?;
+
+    Ok(input.trim().to_string())
+}
+
+fn run_action(action: &Action, profile_name: &str) -> Result<(), CliError> {
+    match action {
+        Action::Use => cli::use_profile(profile_name, false, false, false),
+        Action::Save => cli::save(profile_name, false, false, false),
+        Action::Remove => cli::remove(profile_name, false, false, false),
+        Action::Diff => cli::diff(profile_name, "", false),
+        Action::Rename => {
+            let new_name = read_line(&format!("\nNew name for profile {:?}: ", profile_name))?;
+
+            if new_name.is_empty() {
+                return Err(CliError::cancelled("\nNo profile was renamed.".to_string()));
+            }
+
+            cli::rename(profile_name, &new_name, false)
+        }
+    }
+}
+
+/// Shows every saved profile with its identity, keys and state, and runs
+/// the chosen action on the selected profile.
+pub fn run() -> Result<(), CliError> {
+    if !picker::is_interactive() {
+        return Err(CliError::usage(format!(
+            "{}: The terminal UI needs an interactive terminal.",
+            APP_NAME
+        )));
+    }
+
+    let mut summaries = cli::get_profile_summaries()?;
+    let mut selected = summaries
+        .iter()
+        .position(|summary| summary.active)
+        .unwrap_or(0);
+    let mut message = String::new();
+    let mut key_reader = KeyReader::default();
+
+    loop {
+        let action = {
+            let _screen = Screen::enter()?;
+
+            loop {
+                draw(&summaries, selected, &message).map_err(|e| e.to_string())?;
+                message.clear();
+
+                let action = match key_reader.read_key().map_err(|e| e.to_string())? {
+                    Key::Up => {
+                        selected = selected.saturating_sub(1);
+                        continue;
+                    }
+                    Key::Down => {
+                        selected = (selected + 1).min(summaries.len().saturating_sub(1));
+                        continue;
+                    }
+                    Key::Escape | Key::Char('q') => return Ok(()),
+                    Key::Enter | Key::Char('u') => Action::Use,
+                    Key::Char('s') => Action::Save,
+                    Key::Char('r') => Action::Rename,
+                    Key::Char('x') => Action::Remove,
+                    Key::Char('d') => Action::Diff,
+                    _ => continue,
+                };
+
+                if summaries.is_empty() {
+                    message = "No profile selected.".to_string();
+                    continue;
+                }
+
+                break action;
+            }
+        };
+
+        let profile_name = summaries[selected].name.clone();
+
+        if let Err(e) = run_action(&action, &profile_name) {
+            eprintln!("{}", e.message);
+        }
+
+        read_line("\nPress Enter to return to the profiles.")?;
+
+        summaries = cli::get_profile_summaries()?;
+        selected = summaries
+            .iter()
+            .position(|summary| summary.name == profile_name)
+            .unwrap_or(selected)
+            .min(summaries.len().saturating_sub(1));
+    }
+}
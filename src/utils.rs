@@ -3,17 +3,35 @@ use std::env;
 use std::fs::{self, File};
 use std::io::{self, BufRead, Read, Write};
 use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
 
 use crate::constants::{
-    CONFIG_DIR_NAME, DATA_DIR_NAME, GITCONFIG_FILE_NAME, PREVIOUS_PROFILE_FILE_NAME,
-    READING_DIR_ERR, READING_HASH_FILES_ERR, SSH_DIR, TOGGLE_PREV, TRACKED_FILE_NAMES,
+    ALIASES_FILE_NAME, APP_NAME, AUDIT_LOG_FILE_NAME, BASE_SSH_CONFIG_FILE_NAME, CONFIG_DIR_NAME,
+    DATA_DIR_NAME, GITCONFIG_FILE_NAME, HISTORY_FILE_NAME, LOCK_FILE_NAME, PASSPHRASE_ENV_VAR,
+    PREVIOUS_PROFILE_FILE_NAME, PRIVATE_DIR_MODE, PRIVATE_KEY_MODE, READING_DIR_ERR,
+    READING_HASH_FILES_ERR, SEAL_KEY_FILE_NAME, SETTINGS_FILE_NAME, SSH_CONFIG_FILE_NAME, SSH_DIR,
+    TOGGLE_PREV, TRACKED_FILE_NAMES, TRASH_DIR_NAME,
 };
+use crate::manifest;
+use crate::ssh_config;
+use crate::store;
 
 pub struct AppPaths {
     pub gitconfig_file_path: PathBuf,
     pub data_dir_path: PathBuf,
     pub ssh_dir_path: PathBuf,
     pub previous_profile_file_path: PathBuf,
+    pub settings_file_path: PathBuf,
+    pub base_ssh_config_file_path: PathBuf,
+    pub aliases_file_path: PathBuf,
+    pub history_file_path: PathBuf,
+    pub lock_file_path: PathBuf,
+    pub audit_log_file_path: PathBuf,
+    pub trash_dir_path: PathBuf,
+    pub seal_key_file_path: PathBuf,
 }
 
 pub fn get_app_paths() -> AppPaths {
@@ -25,12 +43,44 @@ pub fn get_app_paths() -> AppPaths {
     let previous_profile_file_path = Path::new(&data_dir_path)
         .join(CONFIG_DIR_NAME)
         .join(PREVIOUS_PROFILE_FILE_NAME);
+    let settings_file_path = Path::new(&data_dir_path)
+        .join(CONFIG_DIR_NAME)
+        .join(SETTINGS_FILE_NAME);
+    let base_ssh_config_file_path = Path::new(&data_dir_path)
+        .join(CONFIG_DIR_NAME)
+        .join(BASE_SSH_CONFIG_FILE_NAME);
+    let aliases_file_path = Path::new(&data_dir_path)
+        .join(CONFIG_DIR_NAME)
+        .join(ALIASES_FILE_NAME);
+    let history_file_path = Path::new(&data_dir_path)
+        .join(CONFIG_DIR_NAME)
+        .join(HISTORY_FILE_NAME);
+    let lock_file_path = Path::new(&data_dir_path)
+        .join(CONFIG_DIR_NAME)
+        .join(LOCK_FILE_NAME);
+    let audit_log_file_path = Path::new(&data_dir_path)
+        .join(CONFIG_DIR_NAME)
+        .join(AUDIT_LOG_FILE_NAME);
+    let trash_dir_path = Path::new(&data_dir_path)
+        .join(CONFIG_DIR_NAME)
+        .join(TRASH_DIR_NAME);
+    let seal_key_file_path = Path::new(&data_dir_path)
+        .join(CONFIG_DIR_NAME)
+        .join(SEAL_KEY_FILE_NAME);
 
     AppPaths {
         gitconfig_file_path,
         data_dir_path,
         ssh_dir_path,
         previous_profile_file_path,
+        settings_file_path,
+        base_ssh_config_file_path,
+        aliases_file_path,
+        history_file_path,
+        lock_file_path,
+        audit_log_file_path,
+        trash_dir_path,
+        seal_key_file_path,
     }
 }
 
@@ -40,6 +90,25 @@ pub struct ProHash {
     pub tracked_file_names: Vec<String>,
 }
 
+pub fn get_current_file_path(app_paths: &AppPaths, filename: &str) -> PathBuf {
+    if filename == GITCONFIG_FILE_NAME {
+        app_paths.gitconfig_file_path.clone()
+    } else {
+        app_paths.ssh_dir_path.join(filename)
+    }
+}
+
+/// Reads a current file. For an assembled `~/.ssh/config`, only the profile
+/// fragment is returned, since the base and unmanaged sections are not part
+/// of any profile.
+pub fn read_current_file(app_paths: &AppPaths, filename: &str) -> io::Result<Vec<u8>> {
+    if filename == SSH_CONFIG_FILE_NAME {
+        return ssh_config::read_current_fragment(app_paths).map(String::into_bytes);
+    }
+
+    fs::read(get_current_file_path(app_paths, filename))
+}
+
 pub fn get_profile_hash(
     app_paths: &AppPaths,
     current_gitconfig_exists: bool,
@@ -49,6 +118,36 @@ pub fn get_profile_hash(
         Some(profile_dir) => {
             let profile_path = app_paths.data_dir_path.join(profile_dir);
 
+            if store::is_sealed(&profile_path) {
+                let sealed_index = store::read_sealed_index(&profile_path)?;
+                let currfiles_prohash =
+                    get_profile_hash(app_paths, current_gitconfig_exists, None)?;
+
+                // the header only holds a MAC of the hash, so the hash is known
+                // when it is the one of the current files, and left empty otherwise
+                let in_use = store::read_seal_key(app_paths).is_some_and(|seal_key| {
+                    sealed_index.has_hash(&seal_key, &currfiles_prohash.hash)
+                });
+
+                return Ok(ProHash {
+                    hash: if in_use {
+                        currfiles_prohash.hash
+                    } else {
+                        String::new()
+                    },
+                    tracked_file_names: sealed_index.tracked_file_names,
+                });
+            }
+
+            if manifest::read_manifest(&profile_path).base.is_some() {
+                let entries = store::read_profile_entries(app_paths, &profile_path, profile_dir)?;
+
+                return Ok(ProHash {
+                    hash: store::get_entries_hash(&entries),
+                    tracked_file_names: entries.into_iter().map(|entry| entry.name).collect(),
+                });
+            }
+
             let all_file_names: Vec<String> = get_files(&profile_path)?;
             let mut tracked_file_names: Vec<String> = all_file_names
                 .into_iter()
@@ -69,19 +168,22 @@ pub fn get_profile_hash(
                 return Err(READING_HASH_FILES_ERR.to_string());
             }
 
-            return Ok(ProHash {
+            Ok(ProHash {
                 hash: profile_hash,
                 tracked_file_names,
-            });
+            })
         }
         None => {
             let all_file_names: Vec<String> =
                 get_files(&app_paths.ssh_dir_path).unwrap_or_else(|_| vec![]);
+            let empty_fragment = ssh_config::read_current_assembled(app_paths)
+                .is_some_and(|assembled| assembled.fragment.is_empty());
             let mut tracked_file_names: Vec<String> = all_file_names
                 .into_iter()
                 .filter(|filename| {
                     TRACKED_FILE_NAMES.contains(&filename.as_str())
                         && filename != GITCONFIG_FILE_NAME
+                        && !(filename == SSH_CONFIG_FILE_NAME && empty_fragment)
                 })
                 .collect();
 
@@ -91,26 +193,20 @@ pub fn get_profile_hash(
 
             tracked_file_names.sort();
 
-            let mut sum_paths: Vec<PathBuf> = vec![];
-
-            tracked_file_names.iter().for_each(|filename| {
-                if filename == GITCONFIG_FILE_NAME {
-                    sum_paths.push(app_paths.gitconfig_file_path.clone());
-                } else {
-                    sum_paths.push(app_paths.ssh_dir_path.join(filename))
-                }
-            });
+            let mut hasher = Sha256::new();
 
-            let profile_hash = get_files_hash(sum_paths).unwrap_or_else(|_| String::from(""));
-
-            if profile_hash.is_empty() {
-                return Err(READING_HASH_FILES_ERR.to_string());
+            for filename in &tracked_file_names {
+                let content = read_current_file(app_paths, filename)
+                    .map_err(|_| READING_HASH_FILES_ERR.to_string())?;
+                hasher.update(content);
             }
 
-            return Ok(ProHash {
+            let profile_hash = format!("{:x}", hasher.finalize());
+
+            Ok(ProHash {
                 hash: profile_hash,
                 tracked_file_names,
-            });
+            })
         }
     }
 }
@@ -172,16 +268,169 @@ pub fn get_files_hash(file_paths: Vec<PathBuf>) -> io::Result<String> {
     Ok(format!("{:x}", hasher.finalize()))
 }
 
-pub fn copy_file(source_file_path: &PathBuf, destination_file_path: &PathBuf) -> io::Result<()> {
-    if let Some(parent) = destination_file_path.parent() {
-        fs::create_dir_all(parent)?;
-    }
+/// Whether a profile name read from a file, not typed by the user, is safe to
+/// use as a directory of `~/.xks`.
+pub fn is_safe_profile_name(profile_name: &str) -> bool {
+    !profile_name.is_empty()
+        && !profile_name.starts_with('.')
+        && !profile_name.contains(['/', '\\'])
+        && !profile_name.contains(char::is_whitespace)
+}
+
+pub fn is_private_key_file_name(filename: &str) -> bool {
+    filename.starts_with("id_") && !filename.ends_with(".pub")
+}
+
+/// Creates `path` and any missing parents, readable only by the owner.
+pub fn create_private_dir_all<T: AsRef<Path>>(path: T) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+
+    #[cfg(unix)]
+    builder.mode(PRIVATE_DIR_MODE);
+
+    builder.create(path)
+}
+
+#[cfg(unix)]
+pub fn get_mode<T: AsRef<Path>>(path: T) -> Option<u32> {
+    fs::metadata(path)
+        .ok()
+        .map(|metadata| metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+pub fn get_mode<T: AsRef<Path>>(_path: T) -> Option<u32> {
+    None
+}
 
-    fs::copy(source_file_path, destination_file_path)?;
+/// Formats permission bits the way `ls -l` does, e.g. `rw-------`.
+pub fn format_mode(mode: u32) -> String {
+    (0..9)
+        .map(|bit| {
+            if mode & (0o400 >> bit) == 0 {
+                '-'
+            } else {
+                ['r', 'w', 'x'][bit % 3]
+            }
+        })
+        .collect()
+}
 
+#[cfg(unix)]
+pub fn set_mode<T: AsRef<Path>>(path: T, mode: u32) -> io::Result<()> {
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+pub fn set_mode<T: AsRef<Path>>(_path: T, _mode: u32) -> io::Result<()> {
     Ok(())
 }
 
+/// Writes a tracked file, creating private keys with owner-only permissions.
+pub fn write_tracked_file<T: AsRef<Path>>(file_path: T, content: &[u8]) -> io::Result<()> {
+    let file_path = file_path.as_ref();
+
+    if let Some(parent) = file_path.parent() {
+        create_private_dir_all(parent)?;
+    }
+
+    let is_private_key = file_path
+        .file_name()
+        .is_some_and(|filename| is_private_key_file_name(&filename.to_string_lossy()));
+
+    if is_private_key {
+        return write_file_with_mode(file_path, content, PRIVATE_KEY_MODE);
+    }
+
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(file_path)?
+        .write_all(content)
+}
+
+/// Writes a file that has `mode` before any content is written, whether it
+/// is created or already exists.
+pub fn write_file_with_mode<T: AsRef<Path>>(
+    file_path: T,
+    content: &[u8],
+    mode: u32,
+) -> io::Result<()> {
+    let file_path = file_path.as_ref();
+
+    if let Some(parent) = file_path.parent() {
+        create_private_dir_all(parent)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    options.mode(mode);
+
+    let mut file = options.open(file_path)?;
+
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(mode))?;
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    file.write_all(content)
+}
+
+#[derive(Debug)]
+pub struct PermissionFix {
+    pub path: PathBuf,
+    pub current_mode: u32,
+    pub expected_mode: u32,
+}
+
+/// Lists the data dir, profile dirs, ssh dir and private keys whose
+/// permissions let the group or others read them.
+pub fn get_permission_fixes(app_paths: &AppPaths) -> Vec<PermissionFix> {
+    let mut fixes: Vec<PermissionFix> = vec![];
+
+    let mut check = |path: PathBuf, expected_mode: u32| {
+        if let Some(current_mode) = get_mode(&path)
+            && current_mode & 0o077 != 0
+        {
+            fixes.push(PermissionFix {
+                path,
+                current_mode,
+                expected_mode,
+            });
+        }
+    };
+
+    let mut key_dirs: Vec<PathBuf> = vec![app_paths.ssh_dir_path.clone()];
+
+    check(app_paths.data_dir_path.clone(), PRIVATE_DIR_MODE);
+    check(
+        app_paths.data_dir_path.join(CONFIG_DIR_NAME),
+        PRIVATE_DIR_MODE,
+    );
+
+    for profile_dir in get_profile_dirs(&app_paths.data_dir_path).unwrap_or_default() {
+        key_dirs.push(app_paths.data_dir_path.join(profile_dir));
+    }
+
+    for key_dir in key_dirs {
+        check(key_dir.clone(), PRIVATE_DIR_MODE);
+
+        for filename in get_files(&key_dir).unwrap_or_default() {
+            if TRACKED_FILE_NAMES.contains(&filename.as_str())
+                && is_private_key_file_name(&filename)
+            {
+                check(key_dir.join(filename), PRIVATE_KEY_MODE);
+            }
+        }
+    }
+
+    fixes
+}
+
 pub fn confirm(prompt: &str) -> bool {
     print!("\n{} [yes/no] (y/n): ", prompt);
     io::stdout().flush().expect("Error: Writing stdout.");
@@ -195,7 +444,7 @@ pub fn confirm(prompt: &str) -> bool {
 }
 
 pub fn read_first_line<T: AsRef<Path>>(file_path: T) -> String {
-    let res = match File::open(&file_path) {
+    match File::open(&file_path) {
         Ok(file) => {
             let mut lines = io::BufReader::new(file).lines();
 
@@ -206,29 +455,89 @@ pub fn read_first_line<T: AsRef<Path>>(file_path: T) -> String {
                 .unwrap_or_default()
         }
         Err(_) => String::new(),
+    }
+}
+
+pub fn read_passphrase(prompt: &str) -> Result<String, String> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_ENV_VAR)
+        && !passphrase.is_empty()
+    {
+        return Ok(passphrase);
+    }
+
+    // prompted on stderr, so it stays out of JSON results on stdout
+    eprint!("\n{}", prompt);
+    io::stderr().flush().expect("Error: Writing stderr.");
+
+    let set_echo = |arg: &str| {
+        Command::new("stty")
+            .arg(arg)
+            .stdin(Stdio::inherit())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
     };
 
-    res
+    let echo_disabled = set_echo("-echo");
+
+    let mut input = String::new();
+    let read_result = io::stdin().read_line(&mut input);
+
+    if echo_disabled {
+        set_echo("echo");
+        eprintln!();
+    }
+
+    read_result.map_err(|_| "Error: Reading input.".to_string())?;
+
+    let passphrase = input.trim_end_matches(['\n', '\r']).to_string();
+
+    if passphrase.is_empty() {
+        return Err(format!("{}: Passphrase cannot be empty.", APP_NAME));
+    }
+
+    Ok(passphrase)
 }
 
-pub fn write_to_file(file_path: PathBuf, content: &str) -> io::Result<()> {
-    if let Some(parent) = file_path.parent() {
-        fs::create_dir_all(parent)?;
+pub fn write_bytes_to_file<T: AsRef<Path>>(file_path: T, content: &[u8]) -> io::Result<()> {
+    if let Some(parent) = file_path.as_ref().parent() {
+        create_private_dir_all(parent)?;
     }
 
     let mut file = File::create(file_path)?;
-    file.write_all(content.as_bytes())?;
+    file.write_all(content)?;
 
     Ok(())
 }
 
+pub fn write_to_file(file_path: PathBuf, content: &str) -> io::Result<()> {
+    write_bytes_to_file(file_path, content.as_bytes())
+}
+
+/// Appends to a file readable by the owner only, creating it when missing.
+pub fn append_to_private_file<T: AsRef<Path>>(file_path: T, content: &str) -> io::Result<()> {
+    let file_path = file_path.as_ref();
+
+    if let Some(parent) = file_path.parent() {
+        create_private_dir_all(parent)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.create(true).append(true);
+
+    #[cfg(unix)]
+    options.mode(PRIVATE_KEY_MODE);
+
+    options.open(file_path)?.write_all(content.as_bytes())
+}
+
 pub fn get_new_use_profile_name(
     app_paths: &AppPaths,
-    profile_dirs: &Vec<String>,
-    current_profile_names: &Vec<String>,
+    profile_dirs: &[String],
+    current_profile_names: &[String],
     profile_name: &str,
 ) -> String {
-    if profile_name == TOGGLE_PREV && profile_dirs.len() > 0 {
+    if profile_name == TOGGLE_PREV && !profile_dirs.is_empty() {
         if profile_dirs.len() == 1 {
             return profile_dirs[0].clone();
         } else if profile_dirs.len() == 2 {